@@ -1,9 +1,25 @@
 use crate::calibration::{CalibrationStep, CalibrationWizard};
-use crate::config::WheelConfig;
-use crate::force_feedback::{ForceFeedback, ForceFeedbackDevice};
-use crate::input::InputReader;
-use crate::virtual_controller::{VirtualController, VirtualXboxController, XboxControllerState};
+use crate::config::{self, AxisBinding, AxisHalf, AxisSplit, SteeringRotation, WheelConfig, DEFAULT_PROFILE_NAME};
+use crate::curve::{CurveShape, ResponseCurve};
+use crate::engine::{Engine, EngineCommand, EngineEvent, EngineSnapshot, MAX_TICK_RATE, MIN_TICK_RATE};
+use crate::force_feedback::{
+    self as ff, RumbleMode, RumbleRecording, TestEffect, VibrationWaveform, MAX_REPLAY_SPEED, MIN_REPLAY_SPEED,
+};
+use crate::input::{InputDevice, InputEvent};
+use crate::mapping::{Binding, BindingRole, InputSource, OutputTarget, SourceCapture};
+use crate::profiles::ProfileStore;
+use crate::shifter;
+use crate::virtual_controller::{
+    ControllerKind, XboxButton, JOYSTICK_AXIS_NAMES, MAX_JOYSTICK_AXES, MAX_JOYSTICK_BUTTONS, MAX_JOYSTICK_HATS,
+    MAX_JOYSTICK_RESOLUTION, MIN_JOYSTICK_RESOLUTION,
+};
 use eframe::egui;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How long to wait for the engine's first device list before starting without one
+const ENGINE_STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum AppMode {
@@ -11,36 +27,92 @@ pub enum AppMode {
     Running,
 }
 
+/// Where a captured input goes once it's been pressed or moved
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CaptureTarget {
+    NewBinding,
+    Binding(usize),
+    /// Forward gear, 0 is first gear
+    ShifterGear(usize),
+    ShifterReverse,
+    ShifterResync,
+    EmergencyStop,
+}
+
+/// Profile action waiting on a name or confirmation in the top panel
+#[derive(Debug, Clone, PartialEq)]
+enum ProfileEdit {
+    New(String),
+    Rename(String),
+    Duplicate(String),
+    Delete,
+}
+
 pub struct RoWheelApp {
     mode: AppMode,
+    profiles: ProfileStore,
+    /// Working copy of the active profile's config
     config: Option<WheelConfig>,
-    input_reader: Option<InputReader>,
-    virtual_controller: Option<Box<dyn VirtualController>>,
-    force_feedback: Option<Box<dyn ForceFeedback>>,
+    engine: Engine,
+    /// Connected devices as last reported by the engine
+    devices: HashMap<String, InputDevice>,
+    /// Latest state from the engine
+    snapshot: EngineSnapshot,
+    /// Whether the engine is currently forwarding raw input to us
+    forwarding_input: bool,
     calibration: Option<CalibrationWizard>,
 
     detected_input_info: String,
     status_message: String,
+    config_error: Option<String>,
     show_debug: bool,
-
-    current_state: XboxControllerState,
+    profile_edit: Option<ProfileEdit>,
+    /// Input being (re)bound and what's been touched so far
+    binding_capture: Option<(CaptureTarget, SourceCapture)>,
+    /// Binding whose axis settings (split, response curve) are open
+    axis_editing: Option<usize>,
+    /// The config was edited with a slider and still needs saving once the drag is over
+    config_dirty: bool,
+    /// Effect types the force feedback device reported
+    ff_effects: Vec<String>,
+    test_strength: f32,
+    /// (torque, steering position) for each frame of the last test
+    test_trace: Vec<(f32, Option<f32>)>,
+    /// Saved rumble recordings, newest first
+    recordings: Vec<PathBuf>,
+    selected_recording: Option<PathBuf>,
+    replay_speed: f32,
+    replay_gain: f32,
 }
 
 impl RoWheelApp {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>) -> anyhow::Result<Self> {
         let fonts = egui::FontDefinitions::default();
         cc.egui_ctx.set_fonts(fonts);
 
-        let config = WheelConfig::load();
+        let (mut profiles, config_error, status_message) = match ProfileStore::load() {
+            Ok(Some(loaded)) => (loaded.config, None, loaded.notice.unwrap_or_default()),
+            Ok(None) => (ProfileStore::default(), None, String::new()),
+            Err(e) => (ProfileStore::default(), Some(format!("Couldn't load config: {}", e)), String::new()),
+        };
+
+        let engine = Engine::start(&profiles.engine)?;
+        let devices = engine.wait_for_devices(ENGINE_STARTUP_TIMEOUT).unwrap_or_else(|| {
+            log::warn!("Engine didn't report devices in time");
+            HashMap::new()
+        });
+
+        if let Some(name) = profiles.best_match(&devices).map(str::to_string) {
+            log::info!("Auto-selected profile {}", name);
+            let _ = profiles.select(&name);
+        }
+
+        let mut config = profiles.active().map(|p| p.config.clone());
         let has_config = config.as_ref().map(|c| c.is_complete()).unwrap_or(false);
 
-        let input_reader = match InputReader::new() {
-            Ok(reader) => Some(reader),
-            Err(e) => {
-                log::error!("Failed to initialize input reader: {}", e);
-                None
-            }
-        };
+        if let Some(config) = config.as_mut() {
+            config.resolve_devices(&devices);
+        }
 
         let mode = if has_config {
             AppMode::Running
@@ -54,81 +126,189 @@ impl RoWheelApp {
             None
         };
 
-        let virtual_controller: Option<Box<dyn VirtualController>> = if mode == AppMode::Running {
-            match VirtualXboxController::new() {
-                Ok(vc) => Some(Box::new(vc)),
-                Err(e) => {
-                    log::error!("Failed to create virtual controller: {}", e);
-                    None
-                }
-            }
-        } else {
-            None
-        };
-
-        Self {
+        let app = Self {
             mode,
+            profiles,
             config,
-            input_reader,
-            virtual_controller,
-            force_feedback: None,
+            engine,
+            devices,
+            snapshot: EngineSnapshot::default(),
+            forwarding_input: false,
             calibration,
             detected_input_info: String::new(),
-            status_message: String::new(),
+            status_message,
+            config_error,
             show_debug: false,
-            current_state: XboxControllerState::default(),
+            profile_edit: None,
+            binding_capture: None,
+            axis_editing: None,
+            config_dirty: false,
+            ff_effects: Vec::new(),
+            test_strength: 0.3,
+            test_trace: Vec::new(),
+            recordings: ff::list_recordings(&config::recordings_dir()),
+            selected_recording: None,
+            replay_speed: 1.0,
+            replay_gain: 1.0,
+        };
+
+        if app.mode == AppMode::Running {
+            app.start_outputs();
         }
+
+        Ok(app)
+    }
+
+    /// Have the engine create the virtual gamepad and open the wheel's force feedback
+    fn start_outputs(&self) {
+        if let Some(ref config) = self.config {
+            self.engine.send(EngineCommand::Start(Box::new(config.clone())));
+            self.start_extra_pads();
+        }
+    }
+
+    /// (Re)create the gamepads for the profiles running next to the active one
+    fn start_extra_pads(&self) {
+        let profiles = self.profiles.running_extra_pads().into_iter().map(|p| (p.name.clone(), p.config.clone())).collect();
+        self.engine.send(EngineCommand::SetExtraPads(profiles));
     }
 
     fn start_calibration(&mut self) {
         self.mode = AppMode::Calibrating;
         self.calibration = Some(CalibrationWizard::new(self.config.clone()));
-        self.virtual_controller = None;
+        self.engine.send(EngineCommand::Stop);
     }
 
     fn finish_calibration(&mut self) {
-        if let Some(ref calibration) = self.calibration {
-            self.config = Some(calibration.config.clone());
-
-            match VirtualXboxController::new() {
-                Ok(vc) => {
-                    self.virtual_controller = Some(Box::new(vc));
-                    self.status_message = "Gamepad connected".to_string();
-                }
-                Err(e) => {
-                    self.status_message = format!("Failed to create gamepad: {}", e);
-                    log::error!("{}", self.status_message);
-                }
+        if let Some(mut calibration) = self.calibration.take() {
+            calibration.record_devices(&self.devices);
+            if self.profiles.active().is_none() {
+                let name = self.profiles.unique_name(DEFAULT_PROFILE_NAME);
+                let _ = self.profiles.create(&name);
             }
-
-            match ForceFeedbackDevice::new(None) {
-                Ok(ff) => {
-                    if ff.is_available() {
-                        self.force_feedback = Some(Box::new(ff));
-                        log::info!("Force feedback initialized");
-                    }
-                }
-                Err(e) => {
-                    log::warn!("Force feedback not available: {}", e);
-                }
+            if let Some(profile) = self.profiles.active_mut() {
+                profile.config = calibration.config.clone();
             }
+            self.save_profiles();
+            self.config = Some(calibration.config);
+
+            self.start_outputs();
         }
 
-        self.calibration = None;
         self.mode = AppMode::Running;
     }
 
-    fn process_inputs(&mut self) {
-        let Some(ref mut reader) = self.input_reader else {
+    fn save_profiles(&mut self) {
+        match self.profiles.save() {
+            Ok(()) => self.config_error = None,
+            Err(e) => {
+                self.status_message = format!("Failed to save config: {}", e);
+                log::error!("{}", self.status_message);
+            }
+        }
+    }
+
+    /// Load the active profile, calibrating it first if it isn't complete
+    fn load_active_profile(&mut self) {
+        self.engine.send(EngineCommand::Stop);
+        self.snapshot = EngineSnapshot::default();
+        self.binding_capture = None;
+        self.axis_editing = None;
+
+        let mut config = self.profiles.active().map(|p| p.config.clone());
+        if let Some(config) = config.as_mut() {
+            config.resolve_devices(&self.devices);
+        }
+        self.config = config;
+
+        if self.config.as_ref().map(|c| c.is_complete()).unwrap_or(false) {
+            self.calibration = None;
+            self.mode = AppMode::Running;
+            self.start_outputs();
+        } else {
+            self.start_calibration();
+        }
+    }
+
+    fn switch_profile(&mut self, name: &str) {
+        if self.profiles.active_profile.as_deref() == Some(name) {
             return;
+        }
+        if let Err(e) = self.profiles.select(name) {
+            self.status_message = e.to_string();
+            return;
+        }
+        log::info!("Switched to profile {}", name);
+        self.save_profiles();
+        self.load_active_profile();
+    }
+
+    fn apply_profile_edit(&mut self, edit: ProfileEdit) {
+        let active = self.profiles.active_profile.clone().unwrap_or_default();
+        let result = match edit {
+            ProfileEdit::New(name) => self.profiles.create(&name),
+            ProfileEdit::Rename(name) => self.profiles.rename(&active, &name),
+            ProfileEdit::Duplicate(name) => self.profiles.duplicate(&active, &name),
+            ProfileEdit::Delete => self.profiles.delete(&active),
         };
 
-        let events = reader.poll();
+        match result {
+            Ok(()) => {
+                self.status_message.clear();
+                self.save_profiles();
+                if self.profiles.active_profile.as_deref() != Some(active.as_str()) {
+                    self.load_active_profile();
+                }
+            }
+            Err(e) => self.status_message = e.to_string(),
+        }
+    }
 
-        if let Some(ref mut calibration) = self.calibration {
-            for event in &events {
-                calibration.process_event(event);
+    /// Pick up device changes, raw input and the latest state from the engine
+    fn process_engine_events(&mut self) {
+        for event in self.engine.events() {
+            match event {
+                EngineEvent::Devices(devices) => self.devices_changed(devices),
+                EngineEvent::Input(event) => self.process_input(&event),
+                EngineEvent::ForceFeedbackEffects(effects) => self.ff_effects = effects,
+                EngineEvent::Recording(recording) => self.save_recording(&recording),
+                EngineEvent::Status(message) => self.status_message = message,
+            }
+        }
+
+        if let Some(snapshot) = self.engine.latest_snapshot() {
+            self.snapshot = snapshot;
+        }
+
+        // Raw input is only needed while calibrating or binding something
+        let wants_input = self.calibration.is_some() || self.binding_capture.is_some();
+        if wants_input != self.forwarding_input {
+            self.forwarding_input = wants_input;
+            self.engine.send(EngineCommand::ForwardInput(wants_input));
+        }
+    }
+
+    fn devices_changed(&mut self, devices: HashMap<String, InputDevice>) {
+        self.devices = devices;
+        if let Some(ref mut config) = self.config {
+            config.resolve_devices(&self.devices);
+        }
+
+        // Only switch when the current profile's devices went away, not whenever anything is plugged in
+        let active_connected = self.config.as_ref()
+            .map(|c| { let (connected, total) = c.connected_bindings(&self.devices); connected == total })
+            .unwrap_or(false);
+        if self.mode == AppMode::Running && !active_connected {
+            if let Some(name) = self.profiles.best_match(&self.devices).map(str::to_string) {
+                log::info!("Auto-selecting profile {} for connected devices", name);
+                self.switch_profile(&name);
             }
+        }
+    }
+
+    fn process_input(&mut self, event: &InputEvent) {
+        if let Some(ref mut calibration) = self.calibration {
+            calibration.process_event(event);
 
             if calibration.needs_axis_detection() {
                 self.detected_input_info = calibration
@@ -141,68 +321,820 @@ impl RoWheelApp {
             }
         }
 
-        if self.mode == AppMode::Running {
-            if let Some(ref config) = self.config {
-                let state = reader.state();
+        if let Some((_, ref mut capture)) = self.binding_capture {
+            capture.process_event(event);
+            // Buttons and hats are done as soon as they're pressed, axes wait for the Done button
+            if capture.captured().is_some() {
+                self.finish_binding_capture();
+            }
+        }
+    }
 
-                let mut xbox_state = XboxControllerState::default();
+    /// Hand the edited config to the engine so changes take effect right away
+    fn push_config(&self) {
+        if let (AppMode::Running, Some(config)) = (&self.mode, &self.config) {
+            self.engine.send(EngineCommand::UpdateConfig(Box::new(config.clone())));
+        }
+    }
 
-                if let Some(ref steering) = config.steering {
-                    if let Some(value) = state.get_axis(&steering.device_id, steering.axis_code) {
-                        xbox_state.left_stick_x = steering.normalize(value);
-                    }
+    /// Write the working config back into the active profile and save it
+    fn commit_config(&mut self) {
+        let Some(config) = self.config.clone() else {
+            return;
+        };
+        self.push_config();
+        if let Some(profile) = self.profiles.active_mut() {
+            profile.config = config;
+        }
+        self.save_profiles();
+    }
+
+    /// Sliders and curve points change every frame while dragged. The engine gets every change
+    /// so it can be felt right away, but only save once they're let go.
+    fn commit_dragged_config(&mut self, ui: &egui::Ui) {
+        if !self.config_dirty {
+            return;
+        }
+        if ui.input(|i| i.pointer.any_down()) {
+            self.push_config();
+        } else {
+            self.config_dirty = false;
+            self.commit_config();
+        }
+    }
+
+    fn render_steering_settings(&mut self, ui: &mut egui::Ui) {
+        let Some(steering) = self.config.as_mut().and_then(|c| c.axis_mut(BindingRole::Steering)) else {
+            return;
+        };
+        let position = self.snapshot.input
+            .get_axis(&steering.device_id, steering.axis_code)
+            .map(|raw| steering.position(raw));
+        let rotation = steering.rotation.get_or_insert_with(SteeringRotation::default);
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            ui.label("Wheel rotation");
+            changed |= ui.add(egui::DragValue::new(&mut rotation.hardware_degrees)
+                .range(90.0..=2520.0).speed(10.0).suffix("°")).changed();
+        });
+        let max_degrees = rotation.hardware_degrees;
+        changed |= ui.add(egui::Slider::new(&mut rotation.degrees, 90.0..=max_degrees)
+            .step_by(10.0).suffix("°").text("Effective rotation")).changed();
+        rotation.degrees = rotation.degrees.min(rotation.hardware_degrees);
+
+        ui.horizontal(|ui| {
+            changed |= ui.checkbox(&mut rotation.soft_lock, "Soft lock").changed();
+            ui.add_enabled_ui(rotation.soft_lock, |ui| {
+                changed |= ui.add(egui::Slider::new(&mut rotation.soft_lock_strength, 0.0..=1.0).text("Strength")).changed();
+            });
+        });
+        if rotation.soft_lock && !self.snapshot.ff_available {
+            ui.label(egui::RichText::new("No force feedback device, soft lock is off").color(egui::Color32::GRAY));
+        }
+
+        if let Some(position) = position {
+            ui.label(format!("Wheel angle: {:.0}°", rotation.angle(position)));
+        }
+
+        self.config_dirty |= changed;
+        self.commit_dragged_config(ui);
+    }
+
+    fn finish_binding_capture(&mut self) {
+        let Some((capture_target, capture)) = self.binding_capture.take() else {
+            return;
+        };
+        let Some(source) = capture.finish() else {
+            self.status_message = "Nothing was moved or pressed".to_string();
+            return;
+        };
+        let Some(ref mut config) = self.config else {
+            return;
+        };
+
+        match capture_target {
+            CaptureTarget::NewBinding => {
+                let joystick = config.controller == ControllerKind::Joystick;
+                let target = match (&source, joystick) {
+                    (InputSource::Axis(_), false) => OutputTarget::RightStickX,
+                    (_, false) => OutputTarget::Button(XboxButton::A),
+                    (InputSource::Axis(_), true) => OutputTarget::JoystickPedal(3),
+                    (_, true) => OutputTarget::JoystickButton(0),
+                };
+                config.bindings.push(Binding {
+                    label: "New Binding".to_string(),
+                    role: None,
+                    source,
+                    target,
+                });
+            }
+            CaptureTarget::Binding(i) => {
+                if let Some(binding) = config.bindings.get_mut(i) {
+                    binding.set_source(source);
+                }
+            }
+            CaptureTarget::ShifterGear(i) => {
+                if let Some(gear) = config.h_shifter.gears.get_mut(i) {
+                    *gear = Some(source);
                 }
+            }
+            CaptureTarget::ShifterReverse => config.h_shifter.reverse = Some(source),
+            CaptureTarget::ShifterResync => config.h_shifter.resync = Some(source),
+            CaptureTarget::EmergencyStop => config.safety.emergency_stop = Some(source),
+        }
+
+        config.record_identities(&self.devices);
+        self.commit_config();
+    }
+
+    fn render_bindings_editor(&mut self, ui: &mut egui::Ui) {
+        let Some(ref mut config) = self.config else {
+            return;
+        };
+        let capturing = self.binding_capture.as_ref().map(|(target, _)| *target);
+        let mut changed = false;
+        let mut remove = None;
+        let mut rebind = None;
+
+        egui::Grid::new("bindings").striped(true).show(ui, |ui| {
+            ui.label("Name");
+            ui.label("Input");
+            ui.label("Output");
+            ui.end_row();
 
-                if let Some(ref clutch) = config.clutch {
-                    if let Some(value) = state.get_axis(&clutch.device_id, clutch.axis_code) {
-                        xbox_state.left_stick_y = clutch.normalize(value);
+            let targets = OutputTarget::all(config.controller, &config.joystick);
+            for (i, binding) in config.bindings.iter_mut().enumerate() {
+                changed |= ui.text_edit_singleline(&mut binding.label).lost_focus();
+                ui.label(binding.source.describe());
+
+                egui::ComboBox::from_id_salt(("binding_target", i))
+                    .selected_text(binding.target.name())
+                    .show_ui(ui, |ui| {
+                        for &target in &targets {
+                            changed |= ui.selectable_value(&mut binding.target, target, target.name()).changed();
+                        }
+                    });
+
+                if matches!(binding.source, InputSource::Axis(_)) {
+                    let open = self.axis_editing == Some(i);
+                    if ui.selectable_label(open, "Tune").clicked() {
+                        self.axis_editing = if open { None } else { Some(i) };
                     }
+                } else {
+                    ui.label("");
                 }
 
-                if let Some(ref throttle) = config.throttle {
-                    if let Some(value) = state.get_axis(&throttle.device_id, throttle.axis_code) {
-                        xbox_state.right_trigger = throttle.normalize_trigger(value);
-                    }
+                if capturing == Some(CaptureTarget::Binding(i)) {
+                    ui.label("Listening...");
+                } else if ui.button("Rebind").clicked() {
+                    rebind = Some(CaptureTarget::Binding(i));
+                }
+                // Roles are needed by calibration and force feedback, recalibrate to change those
+                if binding.role.is_none() && ui.button("Remove").clicked() {
+                    remove = Some(i);
                 }
+                ui.end_row();
+            }
+        });
+
+        let missing = config.bindings.iter().filter(|b| !b.target.exists_on(config.controller, &config.joystick)).count();
+        if missing > 0 {
+            ui.label(egui::RichText::new(format!(
+                "{} binding(s) go to outputs the {} gamepad doesn't have", missing, config.controller.name()
+            )).color(egui::Color32::YELLOW));
+        }
+
+        if config.bindings.iter().any(|b| matches!(b.target, OutputTarget::MouseX | OutputTarget::MouseY)) {
+            ui.horizontal(|ui| {
+                ui.label("Mouse speed");
+                changed |= ui.add(egui::Slider::new(&mut config.keyboard_mouse.mouse_speed, 100.0..=5000.0)
+                    .logarithmic(true)
+                    .suffix(" px/s")).changed();
+            });
+        }
+
+        if let Some(i) = remove {
+            config.bindings.remove(i);
+            self.axis_editing = None;
+            changed = true;
+        }
 
-                if let Some(ref brake) = config.brake {
-                    if let Some(value) = state.get_axis(&brake.device_id, brake.axis_code) {
-                        xbox_state.left_trigger = brake.normalize_trigger(value);
+        let mut other_half = None;
+        if let Some(binding) = self.axis_editing.and_then(|i| config.bindings.get_mut(i)) {
+            let marker = binding.curve_input(&self.snapshot.input);
+            if let InputSource::Axis(ref mut axis) = binding.source {
+                let raw = self.snapshot.input.get_axis(&axis.device_id, axis.axis_code);
+
+                ui.add_space(10.0);
+                ui.label(egui::RichText::new(&binding.label).strong());
+                let (split_changed, add_other_half) = split_editor(ui, axis, raw);
+                self.config_dirty |= split_changed;
+                ui.add_space(10.0);
+                ui.label("Response curve");
+                self.config_dirty |= curve_editor(ui, &mut axis.curve, marker);
+
+                if add_other_half {
+                    let mut other = binding.clone();
+                    other.label = format!("{} (other half)", binding.label);
+                    other.role = None;
+                    other.target = match binding.target {
+                        OutputTarget::RightTrigger => OutputTarget::LeftTrigger,
+                        OutputTarget::StickHalf { axis, positive } => OutputTarget::StickHalf { axis, positive: !positive },
+                        OutputTarget::JoystickPedal(index) => OutputTarget::JoystickPedal((index + 1) % MAX_JOYSTICK_AXES),
+                        _ => OutputTarget::RightTrigger,
+                    };
+                    if let InputSource::Axis(ref mut axis) = other.source {
+                        if let Some(ref mut split) = axis.split {
+                            split.half = split.half.opposite();
+                        }
                     }
+                    other_half = Some(other);
                 }
+            }
+        }
+        if let Some(other) = other_half {
+            config.bindings.push(other);
+            changed = true;
+        }
+
+        if changed {
+            self.commit_config();
+        }
+        self.commit_dragged_config(ui);
+
+        if matches!(capturing, Some(CaptureTarget::NewBinding | CaptureTarget::Binding(_))) {
+            self.render_capture_prompt(ui);
+        } else if ui.button("Add Binding").clicked() {
+            rebind = Some(CaptureTarget::NewBinding);
+        }
+        if let Some(target) = rebind {
+            self.binding_capture = Some((target, SourceCapture::default()));
+        }
+    }
 
-                if let Some(ref shift_up) = config.shift_up {
-                    if let Some(pressed) = state.get_button(&shift_up.device_id, shift_up.button_code) {
-                        xbox_state.buttons.y = pressed;
+    /// What's been touched so far for the input being captured, with Done for axes
+    fn render_capture_prompt(&mut self, ui: &mut egui::Ui) {
+        let Some((_, ref capture)) = self.binding_capture else {
+            return;
+        };
+        let mut finish = false;
+        let mut cancel = false;
+        ui.horizontal(|ui| {
+            ui.label(capture.describe().unwrap_or_else(|| {
+                "Press a button, or move an axis end to end...".to_string()
+            }));
+            finish = ui.button("Done").clicked();
+            cancel = ui.button("Cancel").clicked();
+        });
+
+        if finish {
+            self.finish_binding_capture();
+        } else if cancel {
+            self.binding_capture = None;
+        }
+    }
+
+    fn render_controller_kind(&mut self, ui: &mut egui::Ui) {
+        let Some(ref mut config) = self.config else {
+            return;
+        };
+        let mut changed = false;
+        let mut switch = None;
+
+        ui.horizontal(|ui| {
+            ui.label("Gamepad type");
+            egui::ComboBox::from_id_salt("controller_kind")
+                .selected_text(config.controller.name())
+                .show_ui(ui, |ui| {
+                    for kind in ControllerKind::ALL {
+                        if ui.selectable_label(config.controller == kind, kind.name())
+                            .on_hover_text(kind.description())
+                            .clicked()
+                        {
+                            switch = Some(kind);
+                        }
                     }
-                }
+                });
+        });
+        if let Some(kind) = switch.filter(|&kind| kind != config.controller) {
+            config.set_controller(kind);
+            changed = true;
+        }
+        ui.label(egui::RichText::new(config.controller.description()).color(egui::Color32::GRAY));
+
+        if config.controller == ControllerKind::Joystick {
+            // Every change recreates the device, so only apply once the drag is over
+            let joystick = &mut config.joystick;
+            egui::Grid::new("joystick").show(ui, |ui| {
+                ui.label("Axes");
+                changed |= ui.add(egui::Slider::new(&mut joystick.axes, 1..=MAX_JOYSTICK_AXES)).changed();
+                ui.end_row();
 
-                if let Some(ref shift_down) = config.shift_down {
-                    if let Some(pressed) = state.get_button(&shift_down.device_id, shift_down.button_code) {
-                        xbox_state.buttons.x = pressed;
+                ui.label("Resolution");
+                changed |= ui.add(egui::Slider::new(&mut joystick.resolution, MIN_JOYSTICK_RESOLUTION..=MAX_JOYSTICK_RESOLUTION)
+                    .suffix(" bits")).changed();
+                ui.end_row();
+
+                ui.label("Buttons");
+                changed |= ui.add(egui::Slider::new(&mut joystick.buttons, 1..=MAX_JOYSTICK_BUTTONS)).changed();
+                ui.end_row();
+
+                ui.label("Hats");
+                changed |= ui.add(egui::Slider::new(&mut joystick.hats, 0..=MAX_JOYSTICK_HATS)).changed();
+                ui.end_row();
+            });
+        }
+
+        self.config_dirty |= changed;
+        self.commit_dragged_config(ui);
+    }
+
+    /// One line for each gamepad running next to this one
+    fn render_extra_pads(&self, ui: &mut egui::Ui) {
+        let names = self.profiles.running_extra_pads();
+        if names.is_empty() {
+            return;
+        }
+        for (profile, status) in names.iter().zip(&self.snapshot.extra_pads) {
+            ui.horizontal(|ui| {
+                ui.label(format!("{}:", profile.name));
+                if status.gamepad_connected {
+                    let label = ui.colored_label(egui::Color32::GREEN, "Connected");
+                    if let Some(ref node) = status.gamepad_node {
+                        label.on_hover_text(format!("Virtual gamepad at {}", node.display()));
                     }
+                } else {
+                    ui.colored_label(egui::Color32::RED, "Disconnected");
+                }
+                if profile.config.h_shifter.enabled {
+                    ui.label(format!("Gear: {}", shifter::gear_name(status.gear)));
+                }
+                if status.ff_available {
+                    ui.label(format!("Torque: {:.2}", status.torque));
+                } else {
+                    ui.weak("Rumble not felt, no force feedback wheel of its own");
                 }
+            });
+        }
+        ui.add_space(5.0);
+    }
 
-                self.current_state = xbox_state.clone();
+    fn render_rumble_settings(&mut self, ui: &mut egui::Ui) {
+        let Some(ref mut config) = self.config else {
+            return;
+        };
+        let rumble = &mut config.rumble;
+        let mut changed = false;
 
-                if let Some(ref mut vc) = self.virtual_controller {
-                    if let Err(e) = vc.update(&xbox_state) {
-                        log::error!("Failed to update virtual controller: {}", e);
-                    }
+        egui::ComboBox::from_id_salt("rumble_mode")
+            .selected_text(rumble.mode.name())
+            .show_ui(ui, |ui| {
+                for mode in RumbleMode::ALL {
+                    changed |= ui.selectable_value(&mut rumble.mode, mode, mode.name()).changed();
+                }
+            });
+        ui.label(egui::RichText::new(rumble.mode.description()).color(egui::Color32::GRAY));
 
-                    if let Ok(rumble) = vc.get_rumble() {
-                        if rumble.large_motor > 0.01 || rumble.small_motor > 0.01 {
-                            log::info!("Rumble from game: large={:.2}, small={:.2}",
-                                       rumble.large_motor, rumble.small_motor);
-                        }
-                        if let Some(ref mut ff) = self.force_feedback {
-                            if let Err(e) = ff.apply_rumble(&rumble) {
-                                log::error!("Failed to apply force feedback: {}", e);
-                            }
+        egui::Grid::new("rumble_translation").show(ui, |ui| {
+            ui.label("Gain");
+            changed |= ui.add(egui::Slider::new(&mut rumble.gain, 0.0..=3.0)).changed();
+            ui.end_row();
+
+            ui.label("Offset");
+            changed |= ui.add(egui::Slider::new(&mut rumble.offset, -1.0..=1.0)).changed();
+            ui.end_row();
+
+            ui.label("Max torque");
+            changed |= ui.add(egui::Slider::new(&mut rumble.max_torque, 0.0..=1.0)).changed();
+            ui.end_row();
+
+            if rumble.mode.vibrates() && !rumble.native_vibration {
+                ui.label("Vibration");
+                changed |= ui.add(egui::Slider::new(&mut rumble.vibration_hz, 1.0..=60.0).suffix(" Hz")).changed();
+                ui.end_row();
+            }
+        });
+        changed |= ui.checkbox(&mut rumble.inverted, "Invert direction").changed();
+
+        ui.add_space(5.0);
+        changed |= ui.checkbox(&mut rumble.native_vibration, "Play the small motor as wheel vibration").changed();
+        if rumble.native_vibration {
+            egui::Grid::new("rumble_vibration").show(ui, |ui| {
+                ui.label("Vibration gain");
+                changed |= ui.add(egui::Slider::new(&mut rumble.vibration_gain, 0.0..=3.0)).changed();
+                ui.end_row();
+
+                ui.label("Base frequency");
+                changed |= ui.add(egui::Slider::new(&mut rumble.vibration_hz, 1.0..=60.0).suffix(" Hz")).changed();
+                ui.end_row();
+
+                ui.label("Waveform");
+                egui::ComboBox::from_id_salt("vibration_waveform")
+                    .selected_text(rumble.waveform.name())
+                    .show_ui(ui, |ui| {
+                        for waveform in VibrationWaveform::ALL {
+                            changed |= ui.selectable_value(&mut rumble.waveform, waveform, waveform.name()).changed();
                         }
+                    });
+                ui.end_row();
+            });
+            if self.snapshot.ff_available && !self.snapshot.ff_vibration {
+                ui.label(egui::RichText::new("This wheel has no vibration effect, the small motor is mixed into the torque")
+                    .color(egui::Color32::GRAY));
+            }
+        }
+
+        if cfg!(target_os = "linux") {
+            changed |= ui.checkbox(&mut config.ff_passthrough, "Pass the game's own effects through to the wheel")
+                .on_hover_text("Constant, periodic, spring and damper effects sent to the virtual gamepad play on the wheel as they are, if the wheel supports them. For games other than Roblox. Changing this reconnects the gamepad.")
+                .changed();
+        }
+
+        let game = &self.snapshot.rumble;
+        ui.label(format!("Game: large {:.2}, small {:.2} -> torque {:+.2}, vibration {:.2}",
+            game.large_motor, game.small_motor, self.snapshot.rumble_torque, self.snapshot.rumble_vibration));
+
+        self.config_dirty |= changed;
+        self.commit_dragged_config(ui);
+    }
+
+    fn render_effect_settings(&mut self, ui: &mut egui::Ui) {
+        let Some(ref mut config) = self.config else {
+            return;
+        };
+        let forces = self.snapshot.effect_forces;
+        let mut changed = false;
+
+        let device = &mut config.force_feedback;
+        egui::Grid::new("ff_device").show(ui, |ui| {
+            ui.label("Overall gain");
+            changed |= ui.add(egui::Slider::new(&mut device.gain, 0.0..=1.0)).changed();
+            ui.end_row();
+
+            // DirectInput autocenter is on/off only and stays off
+            if cfg!(target_os = "linux") {
+                ui.label("Wheel autocenter");
+                changed |= ui.add(egui::Slider::new(&mut device.autocenter, 0.0..=1.0)).changed();
+                ui.end_row();
+
+                ui.label("Autocenter on exit");
+                changed |= ui.add(egui::Slider::new(&mut device.exit_autocenter, 0.0..=1.0)).changed();
+                ui.end_row();
+            }
+        });
+        ui.add_space(5.0);
+
+        let effects = &mut config.effects;
+
+        egui::Grid::new("effect_gains").show(ui, |ui| {
+            for (name, gain, force) in [
+                ("Spring", &mut effects.spring, forces.spring),
+                ("Damper", &mut effects.damper, forces.damper),
+                ("Friction", &mut effects.friction, forces.friction),
+                ("Inertia", &mut effects.inertia, forces.inertia),
+            ] {
+                ui.label(name);
+                changed |= ui.add(egui::Slider::new(gain, 0.0..=1.0)).changed();
+                ui.label(format!("{:+.2}", force));
+                ui.end_row();
+            }
+        });
+        if !self.snapshot.ff_available {
+            ui.label(egui::RichText::new("No force feedback device").color(egui::Color32::GRAY));
+        }
+
+        self.config_dirty |= changed;
+        self.commit_dragged_config(ui);
+    }
+
+    fn render_test_bench(&mut self, ui: &mut egui::Ui) {
+        if !self.snapshot.ff_available {
+            ui.label(egui::RichText::new("No force feedback device").color(egui::Color32::GRAY));
+            return;
+        }
+
+        let effects = if self.ff_effects.is_empty() {
+            "not reported".to_string()
+        } else {
+            self.ff_effects.join(", ")
+        };
+        ui.label(format!("Device supports: {}", effects));
+
+        ui.horizontal(|ui| {
+            ui.label("Strength");
+            ui.add(egui::Slider::new(&mut self.test_strength, 0.0..=1.0));
+        });
+
+        let mut run = None;
+        ui.horizontal(|ui| {
+            for effect in TestEffect::ALL {
+                let running = self.snapshot.test == Some(effect);
+                if ui.selectable_label(running, effect.name()).on_hover_text(effect.description()).clicked() {
+                    run = Some(effect);
+                }
+            }
+            if self.snapshot.test.is_some() && ui.button("Stop").clicked() {
+                self.engine.send(EngineCommand::StopTest);
+            }
+        });
+        if let Some(effect) = run {
+            self.test_trace.clear();
+            self.engine.send(EngineCommand::RunTest(effect, self.test_strength));
+        }
+
+        if self.snapshot.test.is_some() {
+            let position = self.config.as_ref().and_then(|c| c.steering_position(&self.snapshot.input));
+            self.test_trace.push((self.snapshot.torque, position));
+        }
+
+        ui.horizontal(|ui| {
+            ui.label(format!("Torque {:+.2}", self.snapshot.torque));
+            torque_meter(ui, self.snapshot.torque);
+        });
+        if !self.test_trace.is_empty() {
+            test_trace_plot(ui, &self.test_trace);
+        }
+        if self.snapshot.ff_stopped {
+            ui.label(egui::RichText::new("Emergency stop is on, nothing will be felt").color(egui::Color32::RED));
+        }
+    }
+
+    fn save_recording(&mut self, recording: &RumbleRecording) {
+        if recording.samples.is_empty() {
+            self.status_message = "Nothing was recorded, the game sent no rumble".to_string();
+            return;
+        }
+        match recording.save(&config::recordings_dir()) {
+            Ok(path) => {
+                self.status_message = format!("Saved recording to {}", path.display());
+                self.recordings = ff::list_recordings(&config::recordings_dir());
+                self.selected_recording = Some(path);
+            }
+            Err(e) => {
+                self.status_message = format!("Failed to save recording: {}", e);
+                log::error!("{}", self.status_message);
+            }
+        }
+    }
+
+    fn render_recording(&mut self, ui: &mut egui::Ui) {
+        ui.label(egui::RichText::new(
+            "Record the rumble a game sends to replay it later while tuning, or to attach to a bug report",
+        ).color(egui::Color32::GRAY));
+
+        ui.horizontal(|ui| {
+            match self.snapshot.recording {
+                Some(elapsed) => {
+                    ui.label(egui::RichText::new(format!("Recording {:.0}s", elapsed.as_secs_f32())).color(egui::Color32::RED));
+                    if ui.button("Stop and Save").clicked() {
+                        self.engine.send(EngineCommand::StopRecording);
+                    }
+                }
+                None => {
+                    if ui.add_enabled(self.snapshot.gamepad_connected, egui::Button::new("Record")).clicked() {
+                        self.engine.send(EngineCommand::StartRecording);
+                    }
+                }
+            }
+        });
+
+        ui.add_space(5.0);
+        ui.horizontal(|ui| {
+            let selected = self.selected_recording.as_ref()
+                .and_then(|p| p.file_name())
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "Choose a recording".to_string());
+            egui::ComboBox::from_id_salt("recording")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for path in &self.recordings {
+                        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+                        ui.selectable_value(&mut self.selected_recording, Some(path.clone()), name);
                     }
+                });
+            if ui.button("Refresh").clicked() {
+                self.recordings = ff::list_recordings(&config::recordings_dir());
+            }
+        });
+
+        let mut adjusted = false;
+        egui::Grid::new("replay").show(ui, |ui| {
+            ui.label("Speed");
+            adjusted |= ui.add(egui::Slider::new(&mut self.replay_speed, MIN_REPLAY_SPEED..=MAX_REPLAY_SPEED).suffix("x")).changed();
+            ui.end_row();
+            ui.label("Gain");
+            adjusted |= ui.add(egui::Slider::new(&mut self.replay_gain, 0.0..=2.0)).changed();
+            ui.end_row();
+        });
+        if adjusted && self.snapshot.replay.is_some() {
+            self.engine.send(EngineCommand::AdjustReplay(self.replay_speed, self.replay_gain));
+        }
+
+        ui.horizontal(|ui| {
+            match self.snapshot.replay {
+                Some(progress) => {
+                    ui.add(egui::ProgressBar::new(progress).desired_width(150.0));
+                    if ui.button("Stop").clicked() {
+                        self.engine.send(EngineCommand::StopReplay);
+                    }
+                }
+                None => {
+                    let ready = self.selected_recording.is_some() && self.snapshot.ff_available;
+                    if ui.add_enabled(ready, egui::Button::new("Replay")).clicked() {
+                        self.start_replay();
+                    }
+                }
+            }
+        });
+        ui.label(egui::RichText::new("Replays go through the Game Rumble settings above, the game's own rumble is ignored meanwhile")
+            .color(egui::Color32::GRAY));
+    }
+
+    fn start_replay(&mut self) {
+        let Some(ref path) = self.selected_recording else {
+            return;
+        };
+        match RumbleRecording::load(path) {
+            Ok(recording) => {
+                self.engine.send(EngineCommand::Replay(Box::new(recording), self.replay_speed, self.replay_gain));
+            }
+            Err(e) => {
+                self.status_message = format!("Failed to load recording: {}", e);
+                log::error!("{}", self.status_message);
+            }
+        }
+    }
+
+    fn render_safety_settings(&mut self, ui: &mut egui::Ui) {
+        let Some(ref mut config) = self.config else {
+            return;
+        };
+        let safety = &mut config.safety;
+        let capturing = self.binding_capture.as_ref().map(|(target, _)| *target);
+        let mut changed = false;
+        let mut capture = false;
+        let mut stop = None;
+
+        ui.horizontal(|ui| {
+            if self.snapshot.ff_stopped {
+                ui.label(egui::RichText::new("Force feedback stopped").color(egui::Color32::RED));
+                if ui.button("Resume").clicked() {
+                    stop = Some(false);
+                }
+            } else {
+                ui.label(format!("Torque: {:+.2}", self.snapshot.torque));
+                if ui.button("Emergency Stop").clicked() {
+                    stop = Some(true);
                 }
             }
+            if self.snapshot.watchdog_tripped {
+                ui.label(egui::RichText::new("Game went quiet, fading its force out").color(egui::Color32::YELLOW));
+            }
+        });
+
+        egui::Grid::new("safety").show(ui, |ui| {
+            ui.label("Stop button");
+            ui.horizontal(|ui| {
+                ui.label(safety.emergency_stop.as_ref().map(|s| s.describe()).unwrap_or_else(|| "Not bound".to_string()));
+                if capturing == Some(CaptureTarget::EmergencyStop) {
+                    ui.label("Listening...");
+                } else if ui.button("Bind").clicked() {
+                    capture = true;
+                }
+                if safety.emergency_stop.is_some() && ui.button("Clear").clicked() {
+                    safety.emergency_stop = None;
+                    changed = true;
+                }
+            });
+            ui.end_row();
+
+            ui.label("Silence timeout");
+            changed |= ui.add(egui::DragValue::new(&mut safety.watchdog_ms).range(0..=30000).speed(50.0).suffix(" ms"))
+                .on_hover_text("Fade the game's force out when it stops updating for this long and no effect of its own is still playing, 0 turns it off")
+                .changed();
+            ui.end_row();
+
+            ui.label("Fade out");
+            changed |= ui.add(egui::DragValue::new(&mut safety.fade_ms).range(0..=5000).speed(10.0).suffix(" ms")).changed();
+            ui.end_row();
+
+            ui.label("Max torque change");
+            changed |= ui.add(egui::Slider::new(&mut safety.slew_rate, 1.0..=100.0).logarithmic(true).suffix(" /s"))
+                .on_hover_text("How fast the force can go from nothing to full, higher is snappier")
+                .changed();
+            ui.end_row();
+        });
+
+        if let Some(stopped) = stop {
+            self.engine.send(EngineCommand::EmergencyStop(stopped));
+        }
+        self.config_dirty |= changed;
+        self.commit_dragged_config(ui);
+
+        if capturing == Some(CaptureTarget::EmergencyStop) {
+            self.render_capture_prompt(ui);
+        }
+        if capture {
+            self.binding_capture = Some((CaptureTarget::EmergencyStop, SourceCapture::default()));
+        }
+    }
+
+    fn render_shifter_settings(&mut self, ui: &mut egui::Ui) {
+        let Some(ref mut config) = self.config else {
+            return;
+        };
+        let shifter = &mut config.h_shifter;
+        let capturing = self.binding_capture.as_ref().map(|(target, _)| *target);
+        let mut changed = false;
+        let mut capture = None;
+        let mut resync = false;
+
+        changed |= ui.checkbox(&mut shifter.enabled, "Emulate an H-shifter with the shift up/down buttons").changed();
+        if !shifter.enabled {
+            if changed {
+                self.commit_config();
+            }
+            return;
+        }
+
+        // Gear rows: reverse, each forward gear, then the resync button
+        let mut rows: Vec<(String, CaptureTarget, &mut Option<InputSource>)> = Vec::new();
+        rows.push(("Reverse".to_string(), CaptureTarget::ShifterReverse, &mut shifter.reverse));
+        for (i, gear) in shifter.gears.iter_mut().enumerate() {
+            rows.push((format!("Gear {}", i + 1), CaptureTarget::ShifterGear(i), gear));
+        }
+        rows.push(("Resync".to_string(), CaptureTarget::ShifterResync, &mut shifter.resync));
+
+        egui::Grid::new("shifter_gears").striped(true).show(ui, |ui| {
+            for (name, target, source) in rows {
+                ui.label(name);
+                ui.label(source.as_ref().map(|s| s.describe()).unwrap_or_else(|| "Not bound".to_string()));
+                if capturing == Some(target) {
+                    ui.label("Listening...");
+                } else if ui.button("Bind").clicked() {
+                    capture = Some(target);
+                }
+                if source.is_some() && ui.button("Clear").clicked() {
+                    *source = None;
+                    changed = true;
+                }
+                ui.end_row();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Add Gear").clicked() {
+                shifter.gears.push(None);
+                changed = true;
+            }
+            if shifter.gears.len() > 1 && ui.button("Remove Gear").clicked() {
+                shifter.gears.pop();
+                changed = true;
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Press");
+            changed |= ui.add(egui::DragValue::new(&mut shifter.pulse_ms).range(10..=500).suffix(" ms")).changed();
+            ui.label("Gap");
+            changed |= ui.add(egui::DragValue::new(&mut shifter.gap_ms).range(10..=500).suffix(" ms")).changed();
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Lowest gear in game");
+            egui::ComboBox::from_id_salt("shifter_lowest_gear")
+                .selected_text(shifter::gear_name(shifter.lowest_gear))
+                .show_ui(ui, |ui| {
+                    for gear in [shifter::REVERSE, shifter::NEUTRAL, 1] {
+                        changed |= ui.selectable_value(&mut shifter.lowest_gear, gear, shifter::gear_name(gear)).changed();
+                    }
+                })
+                .response
+                .on_hover_text("The gear the game starts in and a resync drops to. Neutral on the lever is only shifted into if the game has one, after it's held there for a moment.");
+        });
+
+        ui.horizontal(|ui| {
+            let status = if self.snapshot.resyncing {
+                "Resyncing...".to_string()
+            } else {
+                format!("Gear: {}", shifter::gear_name(self.snapshot.gear))
+            };
+            ui.label(status);
+            resync = ui.button("Resync").on_hover_text("Shift all the way down so the game and RoWheel agree on the gear again").clicked();
+        });
+
+        if resync {
+            self.engine.send(EngineCommand::ResyncShifter);
+        }
+        self.config_dirty |= changed;
+        self.commit_dragged_config(ui);
+
+        if matches!(capturing, Some(CaptureTarget::ShifterGear(_) | CaptureTarget::ShifterReverse | CaptureTarget::ShifterResync)) {
+            self.render_capture_prompt(ui);
+        }
+        if let Some(target) = capture {
+            self.binding_capture = Some((target, SourceCapture::default()));
         }
     }
 
@@ -211,8 +1143,19 @@ impl RoWheelApp {
             ui.vertical_centered(|ui| {
                 ui.add_space(20.0);
                 ui.heading("RoWheel Calibration");
+                if !self.profiles.profiles.is_empty() {
+                    ui.horizontal(|ui| self.render_profile_picker(ui));
+                    if self.profile_edit.is_some() {
+                        self.render_profile_edit(ui);
+                    }
+                }
                 ui.add_space(30.0);
 
+                if let Some(ref error) = self.config_error {
+                    ui.label(egui::RichText::new(error).color(egui::Color32::RED));
+                    ui.add_space(20.0);
+                }
+
                 if let Some(ref calibration) = self.calibration {
                     let progress = calibration.step.index() as f32 / CalibrationStep::TOTAL_STEPS as f32;
 
@@ -235,6 +1178,11 @@ impl RoWheelApp {
                     ui.label(egui::RichText::new(calibration.step.instructions()).size(16.0));
                     ui.add_space(20.0);
 
+                    if calibration.pedals_split && calibration.step.index() > CalibrationStep::BrakeReleased.index() {
+                        ui.label("Throttle and brake share one axis, each reads its own side of the resting point");
+                        ui.add_space(20.0);
+                    }
+
                     if calibration.needs_axis_detection() || calibration.needs_button_detection() {
                         ui.group(|ui| {
                             ui.label("Detected:");
@@ -261,11 +1209,9 @@ impl RoWheelApp {
                             }
                         }
 
-                        if can_skip {
-                            if ui.button(egui::RichText::new("Skip").size(18.0)).clicked() {
-                                if let Some(ref mut cal) = self.calibration {
-                                    cal.skip();
-                                }
+                        if can_skip && ui.button(egui::RichText::new("Skip").size(18.0)).clicked() {
+                            if let Some(ref mut cal) = self.calibration {
+                                cal.skip();
                             }
                         }
                     }
@@ -274,28 +1220,133 @@ impl RoWheelApp {
         });
     }
 
+    fn render_profile_picker(&mut self, ui: &mut egui::Ui) {
+        let active = self.profiles.active_profile.clone().unwrap_or_default();
+        let mut selected = None;
+
+        egui::ComboBox::from_id_salt("profile_picker")
+            .selected_text(&active)
+            .show_ui(ui, |ui| {
+                for profile in &self.profiles.profiles {
+                    if ui.selectable_label(profile.name == active, &profile.name).clicked() {
+                        selected = Some(profile.name.clone());
+                    }
+                }
+            });
+
+        ui.menu_button("Profile", |ui| {
+            if ui.button("New").clicked() {
+                self.profile_edit = Some(ProfileEdit::New(self.profiles.unique_name("New Wheel")));
+                ui.close();
+            }
+            if ui.button("Rename").clicked() {
+                self.profile_edit = Some(ProfileEdit::Rename(active.clone()));
+                ui.close();
+            }
+            if ui.button("Duplicate").clicked() {
+                self.profile_edit = Some(ProfileEdit::Duplicate(self.profiles.unique_name(&active)));
+                ui.close();
+            }
+            if ui.button("Delete").clicked() {
+                self.profile_edit = Some(ProfileEdit::Delete);
+                ui.close();
+            }
+            ui.separator();
+            ui.menu_button("Extra gamepads", |ui| {
+                ui.label("Run these profiles as their own gamepads too");
+                let mut toggled = None;
+                for profile in self.profiles.profiles.iter().filter(|p| p.name != active) {
+                    let mut enabled = self.profiles.extra_pads.contains(&profile.name);
+                    if ui.checkbox(&mut enabled, &profile.name).changed() {
+                        toggled = Some((profile.name.clone(), enabled));
+                    }
+                }
+                if self.profiles.profiles.len() < 2 {
+                    ui.weak("Make another profile for the second wheel or handbrake first");
+                }
+                if let Some((name, enabled)) = toggled {
+                    self.profiles.set_extra_pad(&name, enabled);
+                    self.save_profiles();
+                    if self.mode == AppMode::Running {
+                        self.start_extra_pads();
+                    }
+                }
+            });
+        });
+
+        if let Some(name) = selected {
+            self.switch_profile(&name);
+        }
+    }
+
+    fn render_profile_edit(&mut self, ui: &mut egui::Ui) {
+        let active = self.profiles.active_profile.clone().unwrap_or_default();
+        let mut apply = false;
+        let mut cancel = false;
+
+        ui.horizontal(|ui| {
+            match self.profile_edit {
+                Some(ProfileEdit::New(ref mut name)) => {
+                    ui.label("New profile:");
+                    ui.text_edit_singleline(name);
+                }
+                Some(ProfileEdit::Rename(ref mut name)) => {
+                    ui.label(format!("Rename {} to:", active));
+                    ui.text_edit_singleline(name);
+                }
+                Some(ProfileEdit::Duplicate(ref mut name)) => {
+                    ui.label(format!("Duplicate {} as:", active));
+                    ui.text_edit_singleline(name);
+                }
+                Some(ProfileEdit::Delete) => {
+                    ui.label(format!("Delete profile {}?", active));
+                }
+                None => {}
+            }
+            apply = ui.button("OK").clicked();
+            cancel = ui.button("Cancel").clicked();
+        });
+
+        if apply {
+            if let Some(edit) = self.profile_edit.take() {
+                self.apply_profile_edit(edit);
+            }
+        } else if cancel {
+            self.profile_edit = None;
+        }
+    }
+
     fn render_running_ui(&mut self, ctx: &egui::Context) {
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             ui.horizontal(|ui| {
                 ui.heading("RoWheel");
                 ui.separator();
+                self.render_profile_picker(ui);
+                ui.separator();
                 if ui.button("Recalibrate").clicked() {
                     self.start_calibration();
                 }
                 ui.separator();
                 ui.checkbox(&mut self.show_debug, "Debug");
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                    let status = if self.virtual_controller.as_ref().map(|vc| vc.is_connected()).unwrap_or(false) {
+                    let status = if self.snapshot.gamepad_connected {
                         egui::RichText::new("Connected").color(egui::Color32::GREEN)
                     } else {
                         egui::RichText::new("Disconnected").color(egui::Color32::RED)
                     };
-                    ui.label(status);
+                    let label = ui.label(status);
+                    if let Some(ref node) = self.snapshot.gamepad_node {
+                        label.on_hover_text(format!("Virtual gamepad at {}", node.display()));
+                    }
                 });
             });
+
+            if self.profile_edit.is_some() {
+                self.render_profile_edit(ui);
+            }
         });
 
-        egui::CentralPanel::default().show(ctx, |ui| {
+        egui::CentralPanel::default().show(ctx, |ui| egui::ScrollArea::vertical().show(ui, |ui| {
             if !self.status_message.is_empty() {
                 ui.label(&self.status_message);
                 ui.add_space(10.0);
@@ -303,53 +1354,120 @@ impl RoWheelApp {
 
             ui.heading("Gamepad Output");
             ui.add_space(10.0);
+            if cfg!(target_os = "linux") {
+                self.render_controller_kind(ui);
+                ui.add_space(5.0);
+            }
+            self.render_extra_pads(ui);
 
-            ui.columns(2, |columns| {
-                columns[0].group(|ui| {
-                    ui.label("Left Stick");
-                    ui.horizontal(|ui| {
-                        ui.label(format!("X: {:.2}", self.current_state.left_stick_x));
-                        ui.label(format!("Y: {:.2}", self.current_state.left_stick_y));
+            let state = &self.snapshot.output;
+            let stick = |value: f32| (value + 1.0) / 2.0;
+            let joystick = self.config.as_ref().filter(|c| c.controller == ControllerKind::Joystick).map(|c| &c.joystick);
+            if let Some(layout) = joystick {
+                ui.columns(2, |columns| {
+                    columns[0].group(|ui| {
+                        ui.label("Axes");
+                        for (name, value) in JOYSTICK_AXIS_NAMES.iter().zip(state.joystick.axes).take(layout.axes) {
+                            ui.add(egui::ProgressBar::new(stick(value)).text(format!("{}: {:.3}", name, value)));
+                        }
+                    });
+
+                    columns[1].group(|ui| {
+                        ui.label("Buttons");
+                        ui.horizontal_wrapped(|ui| {
+                            for index in 0..layout.buttons {
+                                let color = if state.joystick.button(index) {
+                                    egui::Color32::YELLOW
+                                } else {
+                                    egui::Color32::DARK_GRAY
+                                };
+                                ui.label(egui::RichText::new(format!("{}", index + 1)).color(color));
+                            }
+                        });
+                        for hat in 0..layout.hats {
+                            let (x, y) = state.joystick.hat(hat);
+                            ui.label(format!("Hat {}: {:+}, {:+}", hat + 1, x, y));
+                        }
                     });
-                    let stick_x = (self.current_state.left_stick_x + 1.0) / 2.0;
-                    ui.add(egui::ProgressBar::new(stick_x).text("Steering"));
                 });
+            } else {
+                ui.columns(2, |columns| {
+                    columns[0].group(|ui| {
+                        ui.label("Sticks");
+                        ui.add(egui::ProgressBar::new(stick(state.left_stick_x))
+                            .text(format!("Left X: {:.2}", state.left_stick_x)));
+                        ui.add(egui::ProgressBar::new(stick(state.left_stick_y))
+                            .text(format!("Left Y: {:.2}", state.left_stick_y)));
+                        ui.add(egui::ProgressBar::new(stick(state.right_stick_x))
+                            .text(format!("Right X: {:.2}", state.right_stick_x)));
+                        ui.add(egui::ProgressBar::new(stick(state.right_stick_y))
+                            .text(format!("Right Y: {:.2}", state.right_stick_y)));
+                    });
 
-                columns[0].add_space(10.0);
+                    columns[0].add_space(10.0);
 
-                columns[0].group(|ui| {
-                    ui.label("Triggers");
-                    ui.add(egui::ProgressBar::new(self.current_state.left_trigger).text("Brake (LT)"));
-                    ui.add(egui::ProgressBar::new(self.current_state.right_trigger).text("Throttle (RT)"));
-                });
+                    columns[0].group(|ui| {
+                        ui.label("Triggers");
+                        ui.add(egui::ProgressBar::new(state.left_trigger).text("LT"));
+                        ui.add(egui::ProgressBar::new(state.right_trigger).text("RT"));
+                    });
 
-                columns[1].group(|ui| {
-                    ui.label("Buttons");
-                    ui.horizontal(|ui| {
-                        let y_color = if self.current_state.buttons.y {
-                            egui::Color32::YELLOW
-                        } else {
-                            egui::Color32::DARK_GRAY
-                        };
-                        let x_color = if self.current_state.buttons.x {
-                            egui::Color32::BLUE
-                        } else {
-                            egui::Color32::DARK_GRAY
-                        };
-
-                        ui.label(egui::RichText::new("Y (Shift Up)").color(y_color));
-                        ui.label(egui::RichText::new("X (Shift Down)").color(x_color));
+                    columns[1].group(|ui| {
+                        ui.label("Buttons");
+                        ui.horizontal_wrapped(|ui| {
+                            for button in XboxButton::ALL {
+                                let color = if state.buttons.get(button) {
+                                    egui::Color32::YELLOW
+                                } else {
+                                    egui::Color32::DARK_GRAY
+                                };
+                                ui.label(egui::RichText::new(button.name()).color(color));
+                            }
+                        });
                     });
                 });
+            }
 
-                columns[1].add_space(10.0);
+            let held = self.snapshot.output.keyboard_mouse.held();
+            if !held.is_empty() {
+                ui.label(format!("Keyboard and mouse: {}", held.join(", ")));
+            }
 
-                columns[1].group(|ui| {
-                    ui.label("Clutch (Left Stick Y)");
-                    let clutch_display = (self.current_state.left_stick_y + 1.0) / 2.0;
-                    ui.add(egui::ProgressBar::new(clutch_display).text("Clutch"));
-                });
-            });
+            ui.add_space(20.0);
+            ui.heading("Steering");
+            ui.add_space(10.0);
+            self.render_steering_settings(ui);
+
+            ui.add_space(20.0);
+            ui.heading("Game Rumble");
+            ui.add_space(10.0);
+            self.render_rumble_settings(ui);
+
+            ui.add_space(20.0);
+            ui.heading("Force Feedback Effects");
+            ui.add_space(10.0);
+            self.render_effect_settings(ui);
+
+            ui.add_space(20.0);
+            ui.collapsing(egui::RichText::new("Force Feedback Test").heading(), |ui| self.render_test_bench(ui));
+
+            ui.add_space(20.0);
+            ui.collapsing(egui::RichText::new("Rumble Recording").heading(), |ui| self.render_recording(ui));
+
+            ui.add_space(20.0);
+            ui.heading("Safety");
+            ui.add_space(10.0);
+            self.render_safety_settings(ui);
+
+            ui.add_space(20.0);
+            ui.heading("H-Shifter");
+            ui.add_space(10.0);
+            self.render_shifter_settings(ui);
+
+            ui.add_space(20.0);
+            ui.heading("Bindings");
+            ui.add_space(10.0);
+            self.render_bindings_editor(ui);
 
             if self.show_debug {
                 ui.add_space(20.0);
@@ -358,65 +1476,58 @@ impl RoWheelApp {
 
                 if let Some(ref config) = self.config {
                     ui.collapsing("Configuration", |ui| {
-                        if let Some(ref s) = config.steering {
-                            let raw_value = self.input_reader.as_ref()
-                                .and_then(|r| r.state().get_axis(&s.device_id, s.axis_code));
-                            ui.label(format!("Steering: axis {} cal=[{:.6}, {:.6}]",
-                                s.axis_code, s.min_value, s.max_value));
-                            ui.label(format!("  raw={:.6} out={:.6}",
-                                raw_value.unwrap_or(0.0), self.current_state.left_stick_x));
-                        }
-                        if let Some(ref t) = config.throttle {
-                            ui.label(format!("Throttle: {} axis {} [{:.2} - {:.2}]",
-                                t.device_name, t.axis_code, t.min_value, t.max_value));
-                        }
-                        if let Some(ref b) = config.brake {
-                            ui.label(format!("Brake: {} axis {} [{:.2} - {:.2}]",
-                                b.device_name, b.axis_code, b.min_value, b.max_value));
-                        }
-                        if let Some(ref c) = config.clutch {
-                            ui.label(format!("Clutch: {} axis {} [{:.2} - {:.2}]",
-                                c.device_name, c.axis_code, c.min_value, c.max_value));
-                        }
-                        if let Some(ref su) = config.shift_up {
-                            ui.label(format!("Shift Up: {} button {} (Y={})",
-                                su.device_name, su.button_code, self.current_state.buttons.y));
-                        }
-                        if let Some(ref sd) = config.shift_down {
-                            ui.label(format!("Shift Down: {} button {} (X={})",
-                                sd.device_name, sd.button_code, self.current_state.buttons.x));
+                        for binding in &config.bindings {
+                            ui.label(format!("{}: {} -> {}",
+                                binding.label, binding.source.describe(), binding.target.name()));
+                            if let InputSource::Axis(ref a) = binding.source {
+                                let raw_value = self.snapshot.input.get_axis(&a.device_id, a.axis_code);
+                                ui.label(format!("  cal=[{:.6}, {:.6}] raw={:.6}",
+                                    a.min_value, a.max_value, raw_value.unwrap_or(0.0)));
+                            }
                         }
                     });
                 }
 
-                if let Some(ref reader) = self.input_reader {
-                    ui.collapsing("Connected Devices", |ui| {
-                        for (id, device) in reader.devices() {
-                            ui.label(format!("{}: {} (FF: {})",
-                                id, device.name, device.has_force_feedback));
-                        }
-                    });
+                ui.collapsing("Connected Devices", |ui| {
+                    for (id, device) in &self.devices {
+                        ui.label(format!("{}: {} (FF: {})",
+                            id, device.name, device.has_force_feedback));
+                    }
+                });
 
-                    ui.collapsing("Raw Button States", |ui| {
-                        let state = reader.state();
-                        for (device_id, buttons) in &state.buttons {
-                            for (code, pressed) in buttons {
-                                if *pressed {
-                                    ui.label(format!("Device {} Button {}: PRESSED", device_id, code));
-                                }
+                ui.collapsing("Raw Button States", |ui| {
+                    for (device_id, buttons) in &self.snapshot.input.buttons {
+                        for (code, pressed) in buttons {
+                            if *pressed {
+                                ui.label(format!("Device {} Button {}: PRESSED", device_id, code));
                             }
                         }
+                    }
+                });
+
+                ui.collapsing("Engine", |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Update rate");
+                        let rate = ui.add(egui::DragValue::new(&mut self.profiles.engine.tick_rate)
+                            .range(MIN_TICK_RATE..=MAX_TICK_RATE).speed(10.0).suffix(" Hz"));
+                        if rate.changed() {
+                            self.engine.send(EngineCommand::SetTickRate(self.profiles.engine.tick_rate));
+                        }
+                        if rate.drag_stopped() || rate.lost_focus() {
+                            self.save_profiles();
+                        }
+                        ui.label(format!("(running at {:.0} Hz)", self.snapshot.tick_rate));
                     });
-                }
+                });
             }
-        });
+        }));
     }
 }
 
 impl eframe::App for RoWheelApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         
-        self.process_inputs();
+        self.process_engine_events();
         ctx.request_repaint();
 
         match self.mode {
@@ -425,3 +1536,214 @@ impl eframe::App for RoWheelApp {
         }
     }
 }
+
+/// Split an axis so the binding only reads one side of a center point.
+/// Returns (changed, add the other half as a new binding).
+fn split_editor(ui: &mut egui::Ui, axis: &mut AxisBinding, raw: Option<f32>) -> (bool, bool) {
+    let mut changed = false;
+    let mut add_other_half = false;
+
+    let mut enabled = axis.split.is_some();
+    if ui.checkbox(&mut enabled, "Split around center").changed() {
+        axis.split = enabled.then(|| AxisSplit {
+            center: raw.unwrap_or((axis.min_value + axis.max_value) / 2.0),
+            half: AxisHalf::Above,
+        });
+        changed = true;
+    }
+
+    let (low, high) = (axis.min_value.min(axis.max_value), axis.min_value.max(axis.max_value));
+    if let Some(ref mut split) = axis.split {
+        ui.horizontal(|ui| {
+            changed |= ui.add(egui::Slider::new(&mut split.center, low..=high).text("Center")).changed();
+            if let Some(raw) = raw {
+                if ui.button("Use current position").clicked() {
+                    split.center = raw.clamp(low, high);
+                    changed = true;
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("split_half")
+                .selected_text(split.half.name())
+                .show_ui(ui, |ui| {
+                    for half in [AxisHalf::Above, AxisHalf::Below] {
+                        changed |= ui.selectable_value(&mut split.half, half, half.name()).changed();
+                    }
+                });
+            add_other_half = ui.button("Add other half").clicked();
+        });
+    }
+
+    (changed, add_other_half)
+}
+
+const CURVE_EDITOR_SIZE: f32 = 220.0;
+
+/// Edit a response curve next to a plot of it, with the live input marked on the plot.
+/// `marker` is (input, output) in 0..1. Returns true if anything changed.
+fn curve_editor(ui: &mut egui::Ui, curve: &mut ResponseCurve, marker: Option<(f32, f32)>) -> bool {
+    let mut changed = false;
+
+    ui.horizontal(|ui| {
+        changed |= curve_plot(ui, curve, marker);
+
+        ui.vertical(|ui| {
+            changed |= ui.add(egui::Slider::new(&mut curve.deadzone, 0.0..=1.0).text("Deadzone")).changed();
+            changed |= ui.add(egui::Slider::new(&mut curve.saturation, 0.0..=1.0).text("Saturation")).changed();
+
+            egui::ComboBox::from_id_salt("curve_shape")
+                .selected_text(curve.shape.name())
+                .show_ui(ui, |ui| {
+                    for shape in [CurveShape::Linear, CurveShape::default_gamma(), CurveShape::default_points()] {
+                        let selected = std::mem::discriminant(&curve.shape) == std::mem::discriminant(&shape);
+                        let name = shape.name();
+                        if ui.selectable_label(selected, name).clicked() && !selected {
+                            curve.shape = shape;
+                            changed = true;
+                        }
+                    }
+                });
+
+            match curve.shape {
+                CurveShape::Linear => {}
+                CurveShape::Gamma { ref mut exponent } => {
+                    changed |= ui.add(egui::Slider::new(exponent, 0.2..=5.0).logarithmic(true).text("Exponent")).changed();
+                }
+                CurveShape::Points { ref mut points } => {
+                    ui.label("Drag points to shape the curve, right-click to remove one");
+                    if ui.button("Add Point").clicked() {
+                        // Split the widest gap so the new point doesn't bend the curve yet
+                        if let Some(i) = (1..points.len())
+                            .max_by(|a, b| (points[*a][0] - points[*a - 1][0]).total_cmp(&(points[*b][0] - points[*b - 1][0])))
+                        {
+                            let (a, b) = (points[i - 1], points[i]);
+                            points.insert(i, [(a[0] + b[0]) / 2.0, (a[1] + b[1]) / 2.0]);
+                            changed = true;
+                        }
+                    }
+                }
+            }
+
+            if let Some((input, output)) = marker {
+                ui.label(format!("Input {:.2} -> Output {:.2}", input, output));
+            }
+        });
+    });
+
+    if changed {
+        curve.sanitize();
+    }
+    changed
+}
+
+/// Bar growing left or right from the middle with the torque
+fn torque_meter(ui: &mut egui::Ui, torque: f32) {
+    let (response, painter) = ui.allocate_painter(egui::vec2(CURVE_EDITOR_SIZE, 16.0), egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 2.0, egui::Color32::from_gray(25));
+
+    let center = rect.center().x;
+    let end = center + torque.clamp(-1.0, 1.0) * rect.width() / 2.0;
+    let bar = egui::Rect::from_x_y_ranges(center.min(end)..=center.max(end), rect.y_range());
+    painter.rect_filled(bar, 0.0, egui::Color32::LIGHT_BLUE);
+    painter.line_segment([egui::pos2(center, rect.top()), egui::pos2(center, rect.bottom())],
+        egui::Stroke::new(1.0, egui::Color32::GRAY));
+}
+
+/// Torque (blue) and steering position (yellow) over the last test, -1 at the bottom to 1 at the top
+fn test_trace_plot(ui: &mut egui::Ui, trace: &[(f32, Option<f32>)]) {
+    let (response, painter) = ui.allocate_painter(egui::vec2(CURVE_EDITOR_SIZE * 2.0, CURVE_EDITOR_SIZE / 2.0), egui::Sense::hover());
+    let rect = response.rect;
+    let to_screen = |i: usize, y: f32| egui::pos2(
+        rect.left() + i as f32 / (trace.len().max(2) - 1) as f32 * rect.width(),
+        rect.center().y - y.clamp(-1.0, 1.0) * rect.height() / 2.0,
+    );
+
+    painter.rect_filled(rect, 0.0, egui::Color32::from_gray(25));
+    painter.line_segment([egui::pos2(rect.left(), rect.center().y), egui::pos2(rect.right(), rect.center().y)],
+        egui::Stroke::new(1.0, egui::Color32::from_gray(60)));
+
+    let torque: Vec<egui::Pos2> = trace.iter().enumerate().map(|(i, (torque, _))| to_screen(i, *torque)).collect();
+    painter.add(egui::Shape::line(torque, egui::Stroke::new(2.0, egui::Color32::LIGHT_BLUE)));
+
+    let position: Vec<egui::Pos2> = trace.iter().enumerate()
+        .filter_map(|(i, (_, position))| position.map(|p| to_screen(i, p)))
+        .collect();
+    painter.add(egui::Shape::line(position, egui::Stroke::new(2.0, egui::Color32::YELLOW)));
+}
+
+fn curve_plot(ui: &mut egui::Ui, curve: &mut ResponseCurve, marker: Option<(f32, f32)>) -> bool {
+    let (response, painter) = ui.allocate_painter(egui::Vec2::splat(CURVE_EDITOR_SIZE), egui::Sense::hover());
+    let rect = response.rect;
+    let to_screen = |x: f32, y: f32| egui::pos2(rect.left() + x * rect.width(), rect.bottom() - y * rect.height());
+    let grid = egui::Stroke::new(1.0, egui::Color32::from_gray(60));
+
+    painter.rect_filled(rect, 0.0, egui::Color32::from_gray(25));
+    for step in 1..4 {
+        let t = step as f32 / 4.0;
+        painter.line_segment([to_screen(t, 0.0), to_screen(t, 1.0)], grid);
+        painter.line_segment([to_screen(0.0, t), to_screen(1.0, t)], grid);
+    }
+    painter.line_segment([to_screen(0.0, 0.0), to_screen(1.0, 1.0)], grid);
+
+    let samples: Vec<egui::Pos2> = (0..=100)
+        .map(|i| {
+            let x = i as f32 / 100.0;
+            to_screen(x, curve.apply(x))
+        })
+        .collect();
+    painter.add(egui::Shape::line(samples, egui::Stroke::new(2.0, egui::Color32::LIGHT_BLUE)));
+
+    if let Some((input, output)) = marker {
+        let guide = egui::Stroke::new(1.0, egui::Color32::YELLOW);
+        painter.line_segment([to_screen(input, 0.0), to_screen(input, output)], guide);
+        painter.line_segment([to_screen(0.0, output), to_screen(input, output)], guide);
+        painter.circle_filled(to_screen(input, output), 5.0, egui::Color32::YELLOW);
+    }
+
+    // Custom points live between the deadzone and saturation, draw them where they land on the input
+    let (deadzone, span) = (curve.deadzone, curve.saturation - curve.deadzone);
+    let mut changed = false;
+    if let CurveShape::Points { ref mut points } = curve.shape {
+        if span <= f32::EPSILON {
+            return false;
+        }
+
+        let mut remove = None;
+        for i in 0..points.len() {
+            let [x, y] = points[i];
+            let center = to_screen(deadzone + x * span, y);
+            let handle = egui::Rect::from_center_size(center, egui::Vec2::splat(12.0));
+            let point_response = ui.interact(handle, response.id.with(i), egui::Sense::click_and_drag());
+
+            if point_response.dragged() {
+                if let Some(pos) = point_response.interact_pointer_pos() {
+                    // Keep points in order by not letting one pass its neighbours
+                    let min_x = if i > 0 { points[i - 1][0] } else { 0.0 };
+                    let max_x = points.get(i + 1).map(|p| p[0]).unwrap_or(1.0);
+                    let input = (pos.x - rect.left()) / rect.width();
+                    points[i][0] = ((input - deadzone) / span).clamp(min_x, max_x);
+                    points[i][1] = ((rect.bottom() - pos.y) / rect.height()).clamp(0.0, 1.0);
+                    changed = true;
+                }
+            }
+            if point_response.secondary_clicked() && points.len() > 2 {
+                remove = Some(i);
+            }
+
+            let color = if point_response.hovered() || point_response.dragged() {
+                egui::Color32::WHITE
+            } else {
+                egui::Color32::LIGHT_BLUE
+            };
+            painter.circle_filled(center, 4.0, color);
+        }
+
+        if let Some(i) = remove {
+            points.remove(i);
+            changed = true;
+        }
+    }
+    changed
+}
@@ -1,5 +1,7 @@
-use crate::config::{AxisBinding, ButtonBinding, WheelConfig};
-use crate::input::InputEvent;
+use crate::config::{AxisBinding, AxisHalf, AxisSplit, ButtonBinding, SteeringRotation, WheelConfig};
+use crate::curve::ResponseCurve;
+use crate::input::{InputDevice, InputEvent};
+use crate::mapping::{BindingRole, InputSource};
 use std::collections::HashMap;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -109,6 +111,9 @@ pub struct CalibrationWizard {
     captured_axis: Option<(String, String, u32, f32)>, // device_id, device_name, axis_code, value
 
     captured_button: Option<(String, String, u32)>, // device_id, device_name, button_code
+
+    /// Throttle and brake turned out to be one axis and were split around its resting point
+    pub pedals_split: bool,
 }
 
 impl CalibrationWizard {
@@ -119,6 +124,7 @@ impl CalibrationWizard {
             axis_trackers: HashMap::new(),
             captured_axis: None,
             captured_button: None,
+            pedals_split: false,
         }
     }
 
@@ -173,19 +179,23 @@ impl CalibrationWizard {
         match self.step {
             CalibrationStep::SteeringLeft => {
                 if let Some((device_id, device_name, axis_code, value)) = self.captured_axis.take() {
-                    self.config.steering = Some(AxisBinding {
+                    self.config.set_binding(BindingRole::Steering, InputSource::Axis(AxisBinding {
                         device_id,
                         device_name,
+                        identity: None,
                         axis_code,
                         min_value: value,
                         max_value: value, // Will be updated in SteeringRight so not rn
                         inverted: false,
-                    });
+                        curve: ResponseCurve::default(),
+                        rotation: Some(SteeringRotation::default()),
+                        split: None,
+                    }));
                 }
             }
             CalibrationStep::SteeringRight => {
-                if let (Some(ref mut steering), Some((_, _, _, value))) =
-                    (&mut self.config.steering, self.captured_axis.take())
+                if let (Some(steering), Some((_, _, _, value))) =
+                    (self.config.axis_mut(BindingRole::Steering), self.captured_axis.take())
                 {
                     steering.max_value = value;
                     // Inverted? (left should be less than right)
@@ -197,19 +207,23 @@ impl CalibrationWizard {
             }
             CalibrationStep::ThrottlePressed => {
                 if let Some((device_id, device_name, axis_code, value)) = self.captured_axis.take() {
-                    self.config.throttle = Some(AxisBinding {
+                    self.config.set_binding(BindingRole::Throttle, InputSource::Axis(AxisBinding {
                         device_id,
                         device_name,
+                        identity: None,
                         axis_code,
                         min_value: value, // Will be swapped if needed
                         max_value: value,
                         inverted: false,
-                    });
+                        curve: ResponseCurve::default(),
+                        rotation: None,
+                        split: None,
+                    }));
                 }
             }
             CalibrationStep::ThrottleReleased => {
-                if let (Some(ref mut throttle), Some((_, _, _, value))) =
-                    (&mut self.config.throttle, self.captured_axis.take())
+                if let (Some(throttle), Some((_, _, _, value))) =
+                    (self.config.axis_mut(BindingRole::Throttle), self.captured_axis.take())
                 {
                     let pressed_value = throttle.max_value;
                     throttle.min_value = value;
@@ -223,19 +237,23 @@ impl CalibrationWizard {
             }
             CalibrationStep::BrakePressed => {
                 if let Some((device_id, device_name, axis_code, value)) = self.captured_axis.take() {
-                    self.config.brake = Some(AxisBinding {
+                    self.config.set_binding(BindingRole::Brake, InputSource::Axis(AxisBinding {
                         device_id,
                         device_name,
+                        identity: None,
                         axis_code,
                         min_value: value,
                         max_value: value,
                         inverted: false,
-                    });
+                        curve: ResponseCurve::default(),
+                        rotation: None,
+                        split: None,
+                    }));
                 }
             }
             CalibrationStep::BrakeReleased => {
-                if let (Some(ref mut brake), Some((_, _, _, value))) =
-                    (&mut self.config.brake, self.captured_axis.take())
+                if let (Some(brake), Some((_, _, _, value))) =
+                    (self.config.axis_mut(BindingRole::Brake), self.captured_axis.take())
                 {
                     let pressed_value = brake.max_value;
                     brake.min_value = value;
@@ -246,22 +264,27 @@ impl CalibrationWizard {
                         brake.inverted = true;
                     }
                 }
+                self.split_shared_pedal_axis();
             }
             CalibrationStep::ClutchPressed => {
                 if let Some((device_id, device_name, axis_code, value)) = self.captured_axis.take() {
-                    self.config.clutch = Some(AxisBinding {
+                    self.config.set_binding(BindingRole::Clutch, InputSource::Axis(AxisBinding {
                         device_id,
                         device_name,
+                        identity: None,
                         axis_code,
                         min_value: value,
                         max_value: value,
                         inverted: false,
-                    });
+                        curve: ResponseCurve::default(),
+                        rotation: None,
+                        split: None,
+                    }));
                 }
             }
             CalibrationStep::ClutchReleased => {
-                if let (Some(ref mut clutch), Some((_, _, _, value))) =
-                    (&mut self.config.clutch, self.captured_axis.take())
+                if let (Some(clutch), Some((_, _, _, value))) =
+                    (self.config.axis_mut(BindingRole::Clutch), self.captured_axis.take())
                 {
                     let pressed_value = clutch.max_value;
                     clutch.min_value = value;
@@ -275,25 +298,22 @@ impl CalibrationWizard {
             }
             CalibrationStep::ShiftUp => {
                 if let Some((device_id, device_name, button_code)) = self.captured_button.take() {
-                    self.config.shift_up = Some(ButtonBinding {
+                    self.config.set_binding(BindingRole::ShiftUp, InputSource::Button(ButtonBinding {
                         device_id,
                         device_name,
+                        identity: None,
                         button_code,
-                    });
+                    }));
                 }
             }
             CalibrationStep::ShiftDown => {
                 if let Some((device_id, device_name, button_code)) = self.captured_button.take() {
-                    self.config.shift_down = Some(ButtonBinding {
+                    self.config.set_binding(BindingRole::ShiftDown, InputSource::Button(ButtonBinding {
                         device_id,
                         device_name,
+                        identity: None,
                         button_code,
-                    });
-                }
-            }
-            CalibrationStep::Complete => {
-                if let Err(e) = self.config.save() {
-                    log::error!("Failed to save config: {}", e);
+                    }));
                 }
             }
             _ => {}
@@ -315,6 +335,62 @@ impl CalibrationWizard {
         self.step = self.step.skip_clutch();
     }
 
+    /// Older wheels report throttle and brake on one axis, read each pedal from its own side of center
+    fn split_shared_pedal_axis(&mut self) {
+        let (Some(throttle), Some(brake)) =
+            (self.config.axis(BindingRole::Throttle), self.config.axis(BindingRole::Brake))
+        else {
+            return;
+        };
+        if throttle.device_id != brake.device_id || throttle.axis_code != brake.axis_code {
+            self.pedals_split = false;
+            return;
+        }
+
+        // (released, pressed) from each pedal's calibration
+        let ends = |axis: &AxisBinding| {
+            if axis.inverted {
+                (axis.max_value, axis.min_value)
+            } else {
+                (axis.min_value, axis.max_value)
+            }
+        };
+        let (throttle_released, throttle_pressed) = ends(throttle);
+        let (brake_released, brake_pressed) = ends(brake);
+
+        let center = (throttle_released + brake_released) / 2.0;
+        let min_value = throttle_pressed.min(brake_pressed);
+        let max_value = throttle_pressed.max(brake_pressed);
+        let throttle_half = if throttle_pressed >= center { AxisHalf::Above } else { AxisHalf::Below };
+        log::info!("Throttle and brake share axis {}, splitting at {:.3}", throttle.axis_code, center);
+
+        for (role, half) in [(BindingRole::Throttle, throttle_half), (BindingRole::Brake, throttle_half.opposite())] {
+            if let Some(axis) = self.config.axis_mut(role) {
+                axis.min_value = min_value;
+                axis.max_value = max_value;
+                axis.inverted = false;
+                axis.split = Some(AxisSplit { center, half });
+            }
+        }
+        self.pedals_split = true;
+    }
+
+    /// Save device identities and pick the wheel that owns the steering axis for force feedback
+    pub fn record_devices(&mut self, devices: &HashMap<String, InputDevice>) {
+        self.config.record_identities(devices);
+
+        let wheel = self.config.binding(BindingRole::Steering)
+            .and_then(|steering| devices.get(steering.source.device().0));
+        if let Some(wheel) = wheel {
+            if !wheel.has_force_feedback {
+                // gilrs doesn't always see FF on DirectInput wheels so let the backend decide
+                log::warn!("{} doesn't report force feedback, using it anyway", wheel.name);
+            }
+            log::info!("Force feedback device: {}", wheel.name);
+            self.config.force_feedback_device = Some(wheel.identity.clone());
+        }
+    }
+
     /// Get info about the detected axis for ui
     pub fn get_detected_axis_info(&self) -> Option<String> {
         self.get_most_moved_axis().map(|tracker| {
@@ -357,3 +433,86 @@ impl CalibrationWizard {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::InputState;
+    use crate::mapping::apply_bindings;
+
+    const AXIS: u32 = 1;
+
+    fn move_axis(wizard: &mut CalibrationWizard, from: f32, to: f32) {
+        for value in [from, to] {
+            wizard.process_event(&InputEvent::AxisMoved {
+                device_id: "0".to_string(),
+                device_name: "Wheel".to_string(),
+                axis_code: AXIS,
+                value,
+            });
+        }
+    }
+
+    /// Run the pedal steps with throttle and brake on one axis, each as (released, pressed)
+    fn calibrate_pedals(throttle: (f32, f32), brake: (f32, f32)) -> CalibrationWizard {
+        let mut wizard = CalibrationWizard::new(None);
+        wizard.step = CalibrationStep::ThrottlePressed;
+        for (released, pressed) in [throttle, brake] {
+            move_axis(&mut wizard, released, pressed);
+            wizard.advance();
+            move_axis(&mut wizard, pressed, released);
+            wizard.advance();
+        }
+        wizard
+    }
+
+    /// (right trigger, left trigger) with the shared axis at `value`
+    fn triggers(wizard: &CalibrationWizard, value: f32) -> (f32, f32) {
+        let mut input = InputState::default();
+        input.axes.insert("0".to_string(), HashMap::from([(AXIS, value)]));
+        let state = apply_bindings(&wizard.config.bindings, &input);
+        (state.right_trigger, state.left_trigger)
+    }
+
+    fn assert_near(actual: (f32, f32), expected: (f32, f32)) {
+        assert!(
+            (actual.0 - expected.0).abs() < 0.01 && (actual.1 - expected.1).abs() < 0.01,
+            "expected {expected:?}, got {actual:?}"
+        );
+    }
+
+    fn split(wizard: &CalibrationWizard, role: BindingRole) -> (f32, AxisHalf) {
+        let axis = wizard.config.axis(role).unwrap();
+        assert!(!axis.inverted);
+        assert_eq!((axis.min_value, axis.max_value), (-1.0, 1.0));
+        let split = axis.split.as_ref().unwrap();
+        (split.center, split.half)
+    }
+
+    #[test]
+    fn splits_throttle_above_brake_below() {
+        // Each pedal rests a little off center, the split goes between them
+        let wizard = calibrate_pedals((0.04, 1.0), (-0.04, -1.0));
+        assert!(wizard.pedals_split);
+        assert_eq!(split(&wizard, BindingRole::Throttle), (0.0, AxisHalf::Above));
+        assert_eq!(split(&wizard, BindingRole::Brake), (0.0, AxisHalf::Below));
+
+        assert_near(triggers(&wizard, 0.0), (0.0, 0.0));
+        assert_near(triggers(&wizard, 1.0), (1.0, 0.0));
+        assert_near(triggers(&wizard, 0.5), (0.5, 0.0));
+        assert_near(triggers(&wizard, -0.5), (0.0, 0.5));
+        assert_near(triggers(&wizard, -1.0), (0.0, 1.0));
+    }
+
+    #[test]
+    fn splits_inverted_pedals() {
+        let wizard = calibrate_pedals((0.0, -1.0), (0.0, 1.0));
+        assert!(wizard.pedals_split);
+        assert_eq!(split(&wizard, BindingRole::Throttle), (0.0, AxisHalf::Below));
+        assert_eq!(split(&wizard, BindingRole::Brake), (0.0, AxisHalf::Above));
+
+        assert_near(triggers(&wizard, 0.0), (0.0, 0.0));
+        assert_near(triggers(&wizard, -1.0), (1.0, 0.0));
+        assert_near(triggers(&wizard, 0.5), (0.0, 0.5));
+    }
+}
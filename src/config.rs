@@ -1,24 +1,346 @@
+use crate::curve::ResponseCurve;
+use crate::force_feedback::{DeviceSettings, EffectGains, RumbleTranslation, SafetySettings};
+use crate::input::{resolve_device, DeviceIdentity, InputDevice, InputState};
+use crate::mapping::{Binding, BindingRole, InputSource};
+use crate::shifter::HShifterConfig;
+use crate::virtual_controller::{ControllerKind, JoystickSettings, KeyboardMouseSettings};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-
-// Configs lowkey don't work rn but if someone could fix then please pr :D
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 const CONFIG_FILENAME: &str = "rowheel_config.json";
+pub const DEFAULT_PROFILE_NAME: &str = "Default";
+const APP_DIR_NAME: &str = "rowheel";
+/// Drop this file next to the executable to keep the config there instead (portable mode)
+const PORTABLE_MARKER: &str = "rowheel.portable";
+
+static CONFIG_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use this config file instead of the default location (`--config <path>`)
+pub fn set_config_override(path: PathBuf) {
+    if CONFIG_OVERRIDE.set(path).is_err() {
+        log::warn!("Config path override was already set");
+    }
+}
+
+fn exe_dir() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|p| p.to_path_buf()))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Per-user config directory, `$XDG_CONFIG_HOME/rowheel` on linux and `%APPDATA%\rowheel` on windows
+fn user_config_dir() -> Option<PathBuf> {
+    #[cfg(windows)]
+    let base = std::env::var_os("APPDATA").map(PathBuf::from);
+
+    #[cfg(not(windows))]
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        // The spec says relative paths are invalid and should be ignored
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+
+    base.map(|base| base.join(APP_DIR_NAME))
+}
+
+/// Bump this and add a step to `MIGRATIONS` whenever the config layout changes
+pub const CONFIG_VERSION: u32 = 3;
+
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// `MIGRATIONS[n]` upgrades a schema version n document to version n + 1
+const MIGRATIONS: &[Migration] = &[migrate_v0_to_v1, migrate_v1_to_v2, migrate_v2_to_v3];
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("couldn't read config: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("config is not valid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("config is from a newer RoWheel (schema {0}, this build knows up to {CONFIG_VERSION})")]
+    TooNew(u32),
+    #[error("couldn't upgrade config from schema {from}: {reason}")]
+    Migration { from: u32, reason: String },
+    #[error("{source}. The old file was backed up to {}", backup.display())]
+    BackedUp {
+        source: Box<ConfigError>,
+        backup: PathBuf,
+    },
+}
+
+/// Version 0 is everything written before the schema was versioned
+fn migrate_v0_to_v1(doc: &mut Value) -> Result<(), String> {
+    let obj = doc.as_object_mut().ok_or("config is not a JSON object")?;
+
+    // force_feedback_device used to be a device path string, now it's a DeviceIdentity
+    if obj.get("force_feedback_device").map(|v| !v.is_object()).unwrap_or(false) {
+        obj.insert("force_feedback_device".to_string(), Value::Null);
+    }
+
+    Ok(())
+}
+
+/// Version 1 held a single WheelConfig, version 2 is a profile store
+fn migrate_v1_to_v2(doc: &mut Value) -> Result<(), String> {
+    let obj = doc.as_object_mut().ok_or("config is not a JSON object")?;
+    obj.remove("schema_version");
+
+    let config = Value::Object(std::mem::take(obj));
+    obj.insert("active_profile".to_string(), Value::from(DEFAULT_PROFILE_NAME));
+    obj.insert(
+        "profiles".to_string(),
+        serde_json::json!([{ "name": DEFAULT_PROFILE_NAME, "config": config }]),
+    );
+
+    Ok(())
+}
+
+/// Version 3 replaced the fixed steering/throttle/brake/clutch/shift fields with a binding table
+fn migrate_v2_to_v3(doc: &mut Value) -> Result<(), String> {
+    // (old field, label, source kind, target) matching what process_inputs used to hard-code
+    let fields = [
+        ("steering", "Steering", "axis", serde_json::json!("left_stick_x")),
+        ("clutch", "Clutch", "axis", serde_json::json!("left_stick_y")),
+        ("throttle", "Throttle", "axis", serde_json::json!("right_trigger")),
+        ("brake", "Brake", "axis", serde_json::json!("left_trigger")),
+        ("shift_up", "Shift Up", "button", serde_json::json!({ "button": "y" })),
+        ("shift_down", "Shift Down", "button", serde_json::json!({ "button": "x" })),
+    ];
+
+    let profiles = doc
+        .get_mut("profiles")
+        .and_then(|p| p.as_array_mut())
+        .ok_or("config has no profile list")?;
+
+    for profile in profiles {
+        let config = profile
+            .get_mut("config")
+            .and_then(|c| c.as_object_mut())
+            .ok_or("profile has no config")?;
+
+        let mut bindings = Vec::new();
+        for (field, label, kind, target) in &fields {
+            let Some(Value::Object(mut source)) = config.remove(*field) else {
+                continue;
+            };
+            source.insert("kind".to_string(), Value::from(*kind));
+            bindings.push(serde_json::json!({
+                "label": label,
+                "role": field,
+                "source": source,
+                "target": target,
+            }));
+        }
+        config.insert("bindings".to_string(), Value::Array(bindings));
+    }
+
+    Ok(())
+}
+
+fn schema_version(doc: &Value) -> u32 {
+    doc.get("schema_version").and_then(|v| v.as_u64()).unwrap_or(0) as u32
+}
+
+/// Upgrade a config document step by step to `CONFIG_VERSION`, returns the version it was
+fn migrate(doc: &mut Value) -> Result<u32, ConfigError> {
+    let version = schema_version(doc);
+
+    if version > CONFIG_VERSION {
+        return Err(ConfigError::TooNew(version));
+    }
+
+    for (from, step) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        step(doc).map_err(|reason| ConfigError::Migration { from: from as u32, reason })?;
+        log::info!("Migrated config from schema {} to {}", from, from + 1);
+    }
+
+    if let Some(obj) = doc.as_object_mut() {
+        obj.insert("schema_version".to_string(), Value::from(CONFIG_VERSION));
+    }
+
+    Ok(version)
+}
+
+/// Move a config we couldn't load out of the way so it isn't overwritten by recalibrating
+fn back_up(path: &Path) -> std::io::Result<PathBuf> {
+    let stamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let backup = path.with_extension(format!("json.{}.bak", stamp));
+    std::fs::rename(path, &backup)?;
+    Ok(backup)
+}
+
+/// A loaded config, and what was done to the file to get it
+pub struct Loaded<T> {
+    pub config: T,
+    /// Set when the file was upgraded from an older schema and written back
+    pub notice: Option<String>,
+}
+
+/// `--config` override, then portable mode, then the per-user config directory
+pub fn config_path() -> PathBuf {
+    if let Some(path) = CONFIG_OVERRIDE.get() {
+        return path.clone();
+    }
+
+    let exe_dir = exe_dir();
+    if exe_dir.join(PORTABLE_MARKER).exists() {
+        return exe_dir.join(CONFIG_FILENAME);
+    }
+
+    user_config_dir()
+        .unwrap_or(exe_dir)
+        .join(CONFIG_FILENAME)
+}
+
+/// Where rumble recordings are saved, beside the config file
+pub fn recordings_dir() -> PathBuf {
+    config_path()
+        .parent()
+        .map(|dir| dir.join("recordings"))
+        .unwrap_or_else(|| PathBuf::from("recordings"))
+}
+
+/// Load and migrate the config file. Ok(None) means there's no config yet. Files that
+/// can't be read or upgraded are backed up before the error is returned, ones from a
+/// newer RoWheel are left alone. An upgraded file is written back with the original kept.
+pub fn load<T: DeserializeOwned>() -> Result<Option<Loaded<T>>, ConfigError> {
+    let mut path = config_path();
+    if !path.exists() {
+        // Older versions always saved next to the executable, pick that up until the next save
+        let legacy = exe_dir().join(CONFIG_FILENAME);
+        if CONFIG_OVERRIDE.get().is_some() || !legacy.exists() {
+            return Ok(None);
+        }
+        log::info!("Using legacy config at {:?}", legacy);
+        path = legacy;
+    }
+
+    let contents = std::fs::read_to_string(&path)?;
+    match parse(&contents) {
+        Ok((config, None)) => {
+            log::info!("Loaded config from {:?}", path);
+            Ok(Some(Loaded { config, notice: None }))
+        }
+        Ok((config, Some((from, doc)))) => {
+            log::info!("Loaded config from {:?}", path);
+            let notice = match write_upgraded(&path, from, &doc) {
+                Ok(original) => format!("Upgraded config from schema {} to {} and saved it, the original is at {}",
+                    from, CONFIG_VERSION, original.display()),
+                Err(e) => {
+                    log::warn!("Failed to save upgraded config: {}", e);
+                    format!("Upgraded config from schema {} to {}, it'll be saved on the next change", from, CONFIG_VERSION)
+                }
+            };
+            Ok(Some(Loaded { config, notice: Some(notice) }))
+        }
+        // Leave it for the RoWheel that wrote it, `save` won't overwrite it either
+        Err(e @ ConfigError::TooNew(_)) => {
+            log::error!("Failed to load config {:?}: {}", path, e);
+            Err(e)
+        }
+        Err(e) => {
+            log::error!("Failed to load config {:?}: {}", path, e);
+            let backup = back_up(&path)?;
+            log::warn!("Backed up invalid config to {:?}", backup);
+            Err(ConfigError::BackedUp {
+                source: Box::new(e),
+                backup,
+            })
+        }
+    }
+}
+
+/// The config, plus the version it was and the upgraded document if it needed migrating
+fn parse<T: DeserializeOwned>(contents: &str) -> Result<(T, Option<(u32, Value)>), ConfigError> {
+    let mut doc: Value = serde_json::from_str(contents)?;
+    let version = migrate(&mut doc)?;
+    let config = serde_json::from_value(doc.clone())?;
+    Ok((config, (version < CONFIG_VERSION).then_some((version, doc))))
+}
+
+/// Write a document upgraded from schema `from` over `path`, returns where the original was copied to
+fn write_upgraded(path: &Path, from: u32, doc: &Value) -> std::io::Result<PathBuf> {
+    let original = path.with_extension(format!("json.v{}.bak", from));
+    std::fs::copy(path, &original)?;
+    std::fs::write(path, serde_json::to_string_pretty(doc)?)?;
+    log::info!("Saved upgraded config to {:?}, original kept at {:?}", path, original);
+    Ok(original)
+}
+
+/// Schema version of the config file at `path`, None if it can't be read
+fn schema_version_on_disk(path: &Path) -> Option<u32> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str::<Value>(&contents).ok().map(|doc| schema_version(&doc))
+}
+
+pub fn save<T: Serialize>(config: &T) -> anyhow::Result<()> {
+    let path = config_path();
+    if let Some(version) = schema_version_on_disk(&path).filter(|&v| v > CONFIG_VERSION) {
+        anyhow::bail!("{} is from a newer RoWheel (schema {}), not overwriting it", path.display(), version);
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let contents = serde_json::to_string_pretty(config)?;
+    std::fs::write(&path, contents)?;
+    log::info!("Saved config to {:?}", path);
+    Ok(())
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AxisBinding {
-    /// UUID for windows and device path for linux
+    /// gilrs id of the device this session, remapped from `identity` on load
     pub device_id: String,
     pub device_name: String,
+    #[serde(default)]
+    pub identity: Option<DeviceIdentity>,
     pub axis_code: u32,
     pub min_value: f32,
     pub max_value: f32,
     pub inverted: bool,
+    #[serde(default)]
+    pub curve: ResponseCurve,
+    /// Steering only, narrows the axis to an effective rotation
+    #[serde(default)]
+    pub rotation: Option<SteeringRotation>,
+    /// Read only one side of the axis, for pedals that share an axis
+    #[serde(default)]
+    pub split: Option<AxisSplit>,
 }
 
 impl AxisBinding {
-    /// Normalize to -1.0..1.0 range based on calibration
+    /// Normalize to -1.0..1.0 range based on calibration, then apply the response curve
     pub fn normalize(&self, raw_value: f32) -> f32 {
+        self.curve.apply_signed(self.normalize_linear(raw_value))
+    }
+
+    /// Normalize to 0.0..1.0 based on calibration, then apply the response curve
+    pub fn normalize_trigger(&self, raw_value: f32) -> f32 {
+        self.curve.apply(self.normalize_trigger_linear(raw_value))
+    }
+
+    /// Normalize to -1.0..1.0 range based on calibration and effective rotation
+    pub fn normalize_linear(&self, raw_value: f32) -> f32 {
+        if let Some(ref split) = self.split {
+            return self.split_value(split, raw_value);
+        }
+        let position = self.position(raw_value);
+        match self.rotation {
+            Some(ref rotation) => (position * rotation.scale()).clamp(-1.0, 1.0),
+            None => position,
+        }
+    }
+
+    /// Where the axis is across its whole calibrated range, -1.0..1.0
+    pub fn position(&self, raw_value: f32) -> f32 {
         let range = (self.max_value - self.min_value) as f64;
         if range.abs() < 0.001 {
             return 0.0;
@@ -34,7 +356,10 @@ impl AxisBinding {
     }
 
     /// Normalize between 0 and 1 just in case scaling is weird
-    pub fn normalize_trigger(&self, raw_value: f32) -> f32 {
+    pub fn normalize_trigger_linear(&self, raw_value: f32) -> f32 {
+        if let Some(ref split) = self.split {
+            return self.split_value(split, raw_value);
+        }
         let range = (self.max_value - self.min_value) as f64;
         if range.abs() < 0.001 {
             return 0.0;
@@ -48,71 +373,350 @@ impl AxisBinding {
         };
         result as f32
     }
+
+    /// How far from center toward this half's end of the calibrated range, 0..1
+    fn split_value(&self, split: &AxisSplit, raw_value: f32) -> f32 {
+        let end = match split.half {
+            AxisHalf::Above => self.max_value,
+            AxisHalf::Below => self.min_value,
+        };
+        let span = end - split.center;
+        if span.abs() < 0.001 {
+            return 0.0;
+        }
+        ((raw_value - split.center) / span).clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AxisHalf {
+    Above,
+    Below,
+}
+
+impl AxisHalf {
+    pub fn opposite(&self) -> Self {
+        match self {
+            Self::Above => Self::Below,
+            Self::Below => Self::Above,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Above => "Above center",
+            Self::Below => "Below center",
+        }
+    }
+}
+
+/// One side of an axis that carries two controls, e.g. throttle above center and brake below
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AxisSplit {
+    /// Raw value both halves start from, usually where the axis rests
+    pub center: f32,
+    pub half: AxisHalf,
+}
+
+/// Effective steering lock, for wheels that turn much further than a game wants
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SteeringRotation {
+    /// Lock to lock rotation of the wheel itself, in degrees
+    pub hardware_degrees: f32,
+    /// Lock to lock rotation that maps onto full stick deflection
+    pub degrees: f32,
+    /// Push the wheel back with force feedback when it's turned past `degrees`
+    pub soft_lock: bool,
+    /// Soft lock force once fully past the stop, 0..1
+    pub soft_lock_strength: f32,
+}
+
+/// How far past the soft lock the end stop ramps up to full strength
+const SOFT_LOCK_RAMP_DEGREES: f32 = 10.0;
+
+impl Default for SteeringRotation {
+    fn default() -> Self {
+        Self {
+            hardware_degrees: 900.0,
+            degrees: 900.0,
+            soft_lock: true,
+            soft_lock_strength: 0.8,
+        }
+    }
+}
+
+impl SteeringRotation {
+    fn scale(&self) -> f32 {
+        if self.degrees <= 0.0 || self.hardware_degrees <= 0.0 {
+            return 1.0;
+        }
+        (self.hardware_degrees / self.degrees).max(1.0)
+    }
+
+    /// Wheel angle in degrees for a -1..1 position across the hardware rotation
+    pub fn angle(&self, position: f32) -> f32 {
+        position * self.hardware_degrees / 2.0
+    }
+
+    /// End stop torque pushing the wheel back toward the soft lock, 0 while inside it
+    pub fn soft_lock_torque(&self, position: f32) -> f32 {
+        if !self.soft_lock || self.degrees >= self.hardware_degrees {
+            return 0.0;
+        }
+        let angle = self.angle(position);
+        let past = angle.abs() - self.degrees / 2.0;
+        if past <= 0.0 {
+            return 0.0;
+        }
+        let force = (past / SOFT_LOCK_RAMP_DEGREES).min(1.0) * self.soft_lock_strength.clamp(0.0, 1.0);
+        -angle.signum() * force
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ButtonBinding {
-    /// UUID for windows and device path for linux
+    /// gilrs id of the device this session, remapped from `identity` on load
     pub device_id: String,
     pub device_name: String,
+    #[serde(default)]
+    pub identity: Option<DeviceIdentity>,
     pub button_code: u32,
 }
 
+/// Find the connected device a binding belongs to.
+/// Configs from before identities were saved fall back to matching by name.
+fn find_binding_device<'a>(
+    device_name: &str,
+    identity: &Option<DeviceIdentity>,
+    devices: &'a HashMap<String, InputDevice>,
+) -> Option<&'a InputDevice> {
+    match identity {
+        Some(identity) => resolve_device(devices, identity),
+        None => resolve_device(devices, &DeviceIdentity {
+            name: device_name.to_string(),
+            ..Default::default()
+        }),
+    }
+}
+
+/// Point a binding at whatever gilrs id its device got this session
+fn resolve_binding(
+    device_id: &mut String,
+    device_name: &str,
+    identity: &Option<DeviceIdentity>,
+    devices: &HashMap<String, InputDevice>,
+) {
+    if let Some(device) = find_binding_device(device_name, identity, devices) {
+        if *device_id != device.id {
+            log::info!("Remapped {} from {} to {}", device_name, device_id, device.id);
+            *device_id = device.id.clone();
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct WheelConfig {
-    pub steering: Option<AxisBinding>,
-    pub throttle: Option<AxisBinding>,
-    pub brake: Option<AxisBinding>,
-    pub clutch: Option<AxisBinding>,
-    pub shift_up: Option<ButtonBinding>,
-    pub shift_down: Option<ButtonBinding>,
-    pub force_feedback_device: Option<String>,
+    pub bindings: Vec<Binding>,
+    /// Device that owns the steering axis, reopened by identity for force feedback
+    pub force_feedback_device: Option<DeviceIdentity>,
+    /// Gain and autocenter the wheel's driver applies
+    pub force_feedback: DeviceSettings,
+    pub h_shifter: HShifterConfig,
+    /// Synthesized road feel on top of the game's force
+    pub effects: EffectGains,
+    /// How the game's rumble turns into wheel torque
+    pub rumble: RumbleTranslation,
+    /// Hand the game's constant, periodic, spring and damper effects straight to the wheel (Linux)
+    pub ff_passthrough: bool,
+    pub safety: SafetySettings,
+    /// Gamepad the game sees
+    pub controller: ControllerKind,
+    /// Layout of the gamepad when it's a generic joystick
+    pub joystick: JoystickSettings,
+    /// How axes bound to the mouse move it
+    pub keyboard_mouse: KeyboardMouseSettings,
 }
 
 impl WheelConfig {
-    pub fn load() -> Option<Self> {
-        let path = Self::config_path();
-        if path.exists() {
-            match std::fs::read_to_string(&path) {
-                Ok(contents) => match serde_json::from_str(&contents) {
-                    Ok(config) => {
-                        log::info!("Loaded config from {:?}", path);
-                        return Some(config);
-                    }
-                    Err(e) => {
-                        log::error!("Failed to parse config: {}", e);
-                    }
-                },
-                Err(e) => {
-                    log::error!("Failed to read config file: {}", e);
-                }
+    pub fn binding(&self, role: BindingRole) -> Option<&Binding> {
+        self.bindings.iter().find(|b| b.role == Some(role))
+    }
+
+    /// The calibrated axis behind a role, None if it's unbound or bound to a button
+    pub fn axis(&self, role: BindingRole) -> Option<&AxisBinding> {
+        match self.binding(role)?.source {
+            InputSource::Axis(ref axis) => Some(axis),
+            _ => None,
+        }
+    }
+
+    /// Where the steering wheel is across its whole rotation, -1.0..1.0
+    pub fn steering_position(&self, input: &InputState) -> Option<f32> {
+        let steering = self.axis(BindingRole::Steering)?;
+        input.get_axis(&steering.device_id, steering.axis_code).map(|raw| steering.position(raw))
+    }
+
+    /// Soft lock torque for where the steering wheel is right now
+    pub fn soft_lock_torque(&self, input: &InputState) -> f32 {
+        let (Some(rotation), Some(position)) = (
+            self.axis(BindingRole::Steering).and_then(|s| s.rotation.as_ref()),
+            self.steering_position(input),
+        ) else {
+            return 0.0;
+        };
+        rotation.soft_lock_torque(position)
+    }
+
+    pub fn axis_mut(&mut self, role: BindingRole) -> Option<&mut AxisBinding> {
+        let binding = self.bindings.iter_mut().find(|b| b.role == Some(role))?;
+        match binding.source {
+            InputSource::Axis(ref mut axis) => Some(axis),
+            _ => None,
+        }
+    }
+
+    /// Bind a role to a new source, keeping its target and curve if the role was already bound
+    pub fn set_binding(&mut self, role: BindingRole, source: InputSource) {
+        if let Some(binding) = self.bindings.iter_mut().find(|b| b.role == Some(role)) {
+            binding.set_source(source);
+        } else {
+            self.bindings.push(Binding {
+                label: role.name().to_string(),
+                role: Some(role),
+                source,
+                target: role.default_target(self.controller),
+            });
+        }
+    }
+
+    /// Whether any binding goes to the virtual keyboard and mouse
+    pub fn uses_keyboard_mouse(&self) -> bool {
+        self.bindings.iter().any(|b| b.target.is_keyboard_mouse())
+    }
+
+    /// Switch gamepads, moving role bindings still on the old pad's default onto the new one's
+    pub fn set_controller(&mut self, controller: ControllerKind) {
+        for binding in &mut self.bindings {
+            let Some(role) = binding.role else {
+                continue;
+            };
+            if binding.target == role.default_target(self.controller) || !binding.target.exists_on(controller, &self.joystick) {
+                binding.target = role.default_target(controller);
             }
         }
-        None
+        self.controller = controller;
     }
 
-    pub fn save(&self) -> anyhow::Result<()> {
-        let path = Self::config_path();
-        let contents = serde_json::to_string_pretty(self)?;
-        std::fs::write(&path, contents)?;
-        log::info!("Saved config to {:?}", path);
-        Ok(())
+    /// Remap every binding to the gilrs ids devices got this session
+    pub fn resolve_devices(&mut self, devices: &HashMap<String, InputDevice>) {
+        let shifter = self.h_shifter.devices_mut().chain(self.safety.devices_mut());
+        for (device_id, device_name, identity) in self.bindings.iter_mut().map(|b| b.source.device_mut()).chain(shifter) {
+            resolve_binding(device_id, device_name, identity, devices);
+        }
     }
 
-    pub fn config_path() -> PathBuf {
-        std::env::current_exe()
-            .ok()
-            .and_then(|p| p.parent().map(|p| p.to_path_buf()))
-            .unwrap_or_else(|| PathBuf::from("."))
-            .join(CONFIG_FILENAME)
+    /// Save the stable identity of every bound device so the config survives reboots
+    pub fn record_identities(&mut self, devices: &HashMap<String, InputDevice>) {
+        let shifter = self.h_shifter.devices_mut().chain(self.safety.devices_mut());
+        for (device_id, _, identity) in self.bindings.iter_mut().map(|b| b.source.device_mut()).chain(shifter) {
+            if let Some(device) = devices.get(device_id.as_str()) {
+                *identity = Some(device.identity.clone());
+            }
+        }
+    }
+
+    /// How many bindings have their device connected right now, out of how many bindings there are
+    pub fn connected_bindings(&self, devices: &HashMap<String, InputDevice>) -> (usize, usize) {
+        let shifter = self.h_shifter.enabled.then(|| self.h_shifter.sources()).into_iter().flatten();
+        let (connected, total) = self.bindings
+            .iter()
+            .map(|b| &b.source)
+            .chain(shifter)
+            .fold((0, 0), |(connected, total), source| {
+                let (_, name, identity) = source.device();
+                let found = find_binding_device(name, identity, devices).is_some();
+                (connected + found as usize, total + 1)
+            });
+        (connected, total)
     }
 
     pub fn is_complete(&self) -> bool {
-        self.steering.is_some()
-            && self.throttle.is_some()
-            && self.brake.is_some()
-            && self.shift_up.is_some()
-            && self.shift_down.is_some()
+        [
+            BindingRole::Steering,
+            BindingRole::Throttle,
+            BindingRole::Brake,
+            BindingRole::ShiftUp,
+            BindingRole::ShiftDown,
+        ]
+        .iter()
+        .all(|role| self.binding(*role).is_some())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiles::ProfileStore;
+
+    /// What an unversioned build wrote: one WheelConfig with the controls as fixed fields
+    const V0_CONFIG: &str = r#"{
+        "force_feedback_device": "/dev/input/event5",
+        "steering": {
+            "device_id": "0",
+            "device_name": "Test Wheel",
+            "axis_code": 0,
+            "min_value": -1.0,
+            "max_value": 1.0,
+            "inverted": false
+        },
+        "shift_up": {
+            "device_id": "0",
+            "device_name": "Test Wheel",
+            "button_code": 5
+        }
+    }"#;
 
+    #[test]
+    fn migrates_v0_to_current() {
+        let (store, upgraded) = parse::<ProfileStore>(V0_CONFIG).unwrap();
+
+        let (from, doc) = upgraded.expect("a v0 config needs migrating");
+        assert_eq!(from, 0);
+        assert_eq!(schema_version(&doc), CONFIG_VERSION);
+        assert_eq!(store.schema_version, CONFIG_VERSION);
+        assert_eq!(store.active_profile.as_deref(), Some(DEFAULT_PROFILE_NAME));
+
+        let [profile] = store.profiles.as_slice() else {
+            panic!("expected one profile, got {}", store.profiles.len());
+        };
+        assert_eq!(profile.name, DEFAULT_PROFILE_NAME);
+        assert!(profile.config.force_feedback_device.is_none());
+
+        let bindings = &profile.config.bindings;
+        assert_eq!(bindings.len(), 2);
+        assert_eq!(bindings[0].role, Some(BindingRole::Steering));
+        assert!(matches!(bindings[0].source, InputSource::Axis(ref a) if a.device_name == "Test Wheel"));
+        assert_eq!(bindings[1].role, Some(BindingRole::ShiftUp));
+        assert!(matches!(bindings[1].source, InputSource::Button(ref b) if b.button_code == 5));
+    }
+
+    #[test]
+    fn migrated_config_round_trips() {
+        let (store, _) = parse::<ProfileStore>(V0_CONFIG).unwrap();
+        let saved = serde_json::to_string(&store).unwrap();
+
+        let (reloaded, upgraded) = parse::<ProfileStore>(&saved).unwrap();
+        assert!(upgraded.is_none());
+        assert_eq!(reloaded.profiles[0].config.bindings.len(), 2);
+    }
+
+    #[test]
+    fn rejects_newer_schema() {
+        let contents = format!(r#"{{ "schema_version": {} }}"#, CONFIG_VERSION + 1);
+        let result = parse::<ProfileStore>(&contents);
+        assert!(matches!(result, Err(ConfigError::TooNew(v)) if v == CONFIG_VERSION + 1));
+    }
 }
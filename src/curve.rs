@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CurveShape {
+    Linear,
+    /// output = input ^ exponent, above 1 is softer near center, below 1 is sharper
+    Gamma { exponent: f32 },
+    /// Straight lines between (input, output) points, both 0..1
+    Points { points: Vec<[f32; 2]> },
+}
+
+impl CurveShape {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Linear => "Linear",
+            Self::Gamma { .. } => "Gamma",
+            Self::Points { .. } => "Custom",
+        }
+    }
+
+    pub fn default_gamma() -> Self {
+        Self::Gamma { exponent: 2.0 }
+    }
+
+    pub fn default_points() -> Self {
+        Self::Points {
+            points: vec![[0.0, 0.0], [0.5, 0.5], [1.0, 1.0]],
+        }
+    }
+
+    fn apply(&self, x: f32) -> f32 {
+        match self {
+            Self::Linear => x,
+            Self::Gamma { exponent } => x.powf(exponent.max(0.01)),
+            Self::Points { points } => interpolate(points, x),
+        }
+    }
+}
+
+/// Piecewise-linear lookup, points are expected sorted by input
+fn interpolate(points: &[[f32; 2]], x: f32) -> f32 {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return x;
+    };
+    if x <= first[0] {
+        return first[1];
+    }
+    if x >= last[0] {
+        return last[1];
+    }
+
+    for pair in points.windows(2) {
+        let ([x0, y0], [x1, y1]) = (pair[0], pair[1]);
+        if x <= x1 {
+            if (x1 - x0).abs() < f32::EPSILON {
+                return y1;
+            }
+            return y0 + (y1 - y0) * (x - x0) / (x1 - x0);
+        }
+    }
+    last[1]
+}
+
+/// How an axis responds between its calibrated ends
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResponseCurve {
+    /// Input below this reads as 0
+    pub deadzone: f32,
+    /// Input above this reads as 1
+    pub saturation: f32,
+    pub shape: CurveShape,
+}
+
+impl Default for ResponseCurve {
+    fn default() -> Self {
+        Self {
+            deadzone: 0.0,
+            saturation: 1.0,
+            shape: CurveShape::Linear,
+        }
+    }
+}
+
+impl ResponseCurve {
+    /// Map a 0..1 input through the deadzone, saturation and shape
+    pub fn apply(&self, x: f32) -> f32 {
+        let x = x.clamp(0.0, 1.0);
+        let span = self.saturation - self.deadzone;
+        let x = if span <= f32::EPSILON {
+            if x >= self.saturation { 1.0 } else { 0.0 }
+        } else {
+            ((x - self.deadzone) / span).clamp(0.0, 1.0)
+        };
+        self.shape.apply(x).clamp(0.0, 1.0)
+    }
+
+    /// Same as `apply` for a -1..1 stick value, mirrored around center
+    pub fn apply_signed(&self, x: f32) -> f32 {
+        x.signum() * self.apply(x.abs())
+    }
+
+    /// Keep custom points sorted and inside 0..1 after editing
+    pub fn sanitize(&mut self) {
+        self.deadzone = self.deadzone.clamp(0.0, 1.0);
+        self.saturation = self.saturation.clamp(self.deadzone, 1.0);
+        if let CurveShape::Points { ref mut points } = self.shape {
+            for point in points.iter_mut() {
+                point[0] = point[0].clamp(0.0, 1.0);
+                point[1] = point[1].clamp(0.0, 1.0);
+            }
+            points.sort_by(|a, b| a[0].total_cmp(&b[0]));
+        }
+    }
+}
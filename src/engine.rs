@@ -0,0 +1,797 @@
+use crate::config::WheelConfig;
+use crate::force_feedback::{
+    EffectForces, EffectSynth, ForceFeedback, ForceFeedbackDevice, RumbleRecorder, RumbleRecording, RumbleReplay,
+    RumbleTranslator, TestEffect, TestRun, TorqueGuard,
+};
+use crate::input::{InputDevice, InputEvent, InputReader, InputState};
+use crate::mapping::{self, BindingRole};
+use crate::shifter::{HShifter, ShiftOutput};
+use crate::virtual_controller::{
+    self, ControllerKind, FfPassthrough, RumbleState, VirtualController, VirtualKeyboardMouse, XboxControllerState,
+};
+use crossbeam_channel::{Receiver, Sender, TryRecvError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+pub const MIN_TICK_RATE: u32 = 100;
+pub const MAX_TICK_RATE: u32 = 2000;
+/// How often the GUI gets a snapshot, it can't show more than this anyway
+const SNAPSHOT_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Machine-wide settings for the engine thread, shared by every profile
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngineSettings {
+    /// Input, gamepad and force feedback updates per second
+    pub tick_rate: u32,
+}
+
+impl Default for EngineSettings {
+    fn default() -> Self {
+        Self { tick_rate: 500 }
+    }
+}
+
+pub enum EngineCommand {
+    /// Create the gamepad and open force feedback for this config
+    Start(Box<WheelConfig>),
+    /// Swap in an edited config without recreating the outputs
+    UpdateConfig(Box<WheelConfig>),
+    /// Drop the gamepad and force feedback (calibrating)
+    Stop,
+    /// Forward every input event, for calibration and binding capture
+    ForwardInput(bool),
+    ResyncShifter,
+    /// Cut (true) or restore (false) all force feedback
+    EmergencyStop(bool),
+    /// Play a test effect at a strength (0 to 1) instead of the game's force
+    RunTest(TestEffect, f32),
+    StopTest,
+    /// Record the game's rumble until `StopRecording`
+    StartRecording,
+    /// Finish the recording and send it back as `EngineEvent::Recording`
+    StopRecording,
+    /// Play a recording in place of the game's rumble, at a speed and gain
+    Replay(Box<RumbleRecording>, f32, f32),
+    /// Change the speed and gain of the replay that's playing
+    AdjustReplay(f32, f32),
+    StopReplay,
+    /// Run a gamepad for each of these profiles next to the started one, each with
+    /// its own bindings and its rumble going to its own wheel. Pads already running
+    /// for a profile are kept.
+    SetExtraPads(Vec<(String, WheelConfig)>),
+    SetTickRate(u32),
+    Shutdown,
+}
+
+pub enum EngineEvent {
+    /// Raw input, only sent while forwarding is on
+    Input(InputEvent),
+    /// Connected devices, sent at startup and whenever one is plugged in or removed
+    Devices(HashMap<String, InputDevice>),
+    /// Effect types the force feedback device reported, sent whenever it's opened
+    ForceFeedbackEffects(Vec<String>),
+    /// A finished rumble recording, for the GUI to save
+    Recording(Box<RumbleRecording>),
+    Status(String),
+}
+
+/// What the engine is doing right now, for the GUI to show
+#[derive(Debug, Clone, Default)]
+pub struct EngineSnapshot {
+    pub output: XboxControllerState,
+    pub input: InputState,
+    pub gamepad_connected: bool,
+    /// The /dev/input node the gamepad became, on Linux
+    pub gamepad_node: Option<PathBuf>,
+    pub ff_available: bool,
+    pub gear: i32,
+    pub resyncing: bool,
+    pub effect_forces: EffectForces,
+    /// Last rumble the game sent and the torque it became
+    pub rumble: RumbleState,
+    pub rumble_torque: f32,
+    /// Small motor strength going to the wheel's vibration effect
+    pub rumble_vibration: f32,
+    /// Whether the wheel can play vibration itself
+    pub ff_vibration: bool,
+    pub ff_stopped: bool,
+    /// The game went quiet and its force is being faded out
+    pub watchdog_tripped: bool,
+    /// Torque actually sent to the wheel
+    pub torque: f32,
+    /// Test effect playing instead of the game's force
+    pub test: Option<TestEffect>,
+    /// How long the rumble has been recording for
+    pub recording: Option<Duration>,
+    /// How far through the replay is, 0 to 1
+    pub replay: Option<f32>,
+    /// The gamepads running next to this one, in the order they were asked for
+    pub extra_pads: Vec<PadStatus>,
+    /// Ticks per second actually reached
+    pub tick_rate: f32,
+}
+
+/// How one of the extra gamepads is doing
+#[derive(Debug, Clone, Default)]
+pub struct PadStatus {
+    pub gamepad_connected: bool,
+    pub gamepad_node: Option<PathBuf>,
+    /// Whether it has a wheel of its own to send rumble to
+    pub ff_available: bool,
+    pub gear: i32,
+    pub torque: f32,
+}
+
+/// Handle to the thread that polls input and drives the gamepad and force feedback,
+/// so they keep running at a steady rate whatever the window is doing
+pub struct Engine {
+    commands: Sender<EngineCommand>,
+    events: Receiver<EngineEvent>,
+    snapshots: Receiver<EngineSnapshot>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Engine {
+    pub fn start(settings: &EngineSettings) -> anyhow::Result<Self> {
+        let (command_tx, command_rx) = crossbeam_channel::unbounded();
+        let (event_tx, event_rx) = crossbeam_channel::unbounded();
+        // The GUI only wants the latest snapshot, drop them while it isn't drawing
+        let (snapshot_tx, snapshot_rx) = crossbeam_channel::bounded(1);
+        let tick_rate = settings.tick_rate;
+
+        let thread = std::thread::Builder::new()
+            .name("rowheel-engine".to_string())
+            .spawn(move || {
+                // gilrs has to live on the thread that polls it
+                let reader = match InputReader::new() {
+                    Ok(reader) => Some(reader),
+                    Err(e) => {
+                        log::error!("Failed to initialize input reader: {}", e);
+                        let _ = event_tx.send(EngineEvent::Status(format!("Failed to read input devices: {}", e)));
+                        None
+                    }
+                };
+                EngineThread::new(reader, tick_rate, command_rx, event_tx, snapshot_tx).run();
+            })?;
+
+        Ok(Self {
+            commands: command_tx,
+            events: event_rx,
+            snapshots: snapshot_rx,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn send(&self, command: EngineCommand) {
+        if self.commands.send(command).is_err() {
+            log::error!("Engine thread is gone");
+        }
+    }
+
+    /// Wait for the first device list so startup can pick a profile
+    pub fn wait_for_devices(&self, timeout: Duration) -> Option<HashMap<String, InputDevice>> {
+        match self.events.recv_timeout(timeout) {
+            Ok(EngineEvent::Devices(devices)) => Some(devices),
+            _ => None,
+        }
+    }
+
+    pub fn events(&self) -> Vec<EngineEvent> {
+        self.events.try_iter().collect()
+    }
+
+    pub fn latest_snapshot(&self) -> Option<EngineSnapshot> {
+        self.snapshots.try_iter().last()
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let _ = self.commands.send(EngineCommand::Shutdown);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Engine thread panicked");
+            }
+        }
+    }
+}
+
+struct EngineThread {
+    reader: Option<InputReader>,
+    tick: Duration,
+    commands: Receiver<EngineCommand>,
+    events: Sender<EngineEvent>,
+    snapshots: Sender<EngineSnapshot>,
+
+    /// The active profile's pad, the one the GUI shows and tests
+    pad: Option<Pad>,
+    /// Pads for the profiles running next to it, in the order they were asked for
+    extra_pads: Vec<Pad>,
+    /// Kept here so pads created while stopped stay stopped
+    emergency_stopped: bool,
+    forward_input: bool,
+
+    last_snapshot: Instant,
+    ticks_since_snapshot: u32,
+    measured_rate: f32,
+}
+
+impl EngineThread {
+    fn new(
+        reader: Option<InputReader>,
+        tick_rate: u32,
+        commands: Receiver<EngineCommand>,
+        events: Sender<EngineEvent>,
+        snapshots: Sender<EngineSnapshot>,
+    ) -> Self {
+        Self {
+            reader,
+            tick: tick_duration(tick_rate),
+            commands,
+            events,
+            snapshots,
+            pad: None,
+            extra_pads: Vec::new(),
+            emergency_stopped: false,
+            forward_input: false,
+            last_snapshot: Instant::now(),
+            ticks_since_snapshot: 0,
+            measured_rate: 0.0,
+        }
+    }
+
+    fn run(mut self) {
+        let devices = self.reader.as_ref().map(|r| r.devices().clone()).unwrap_or_default();
+        let _ = self.events.send(EngineEvent::Devices(devices));
+
+        let mut next_tick = Instant::now();
+        loop {
+            if !self.handle_commands() {
+                break;
+            }
+            self.tick();
+
+            next_tick += self.tick;
+            let now = Instant::now();
+            if next_tick > now {
+                std::thread::sleep(next_tick - now);
+            } else if now - next_tick > self.tick * 10 {
+                // Fell way behind (suspend, debugger), don't try to catch up
+                next_tick = now;
+            }
+        }
+
+        log::info!("Engine thread stopped");
+    }
+
+    /// Returns false once it's time to shut down
+    fn handle_commands(&mut self) -> bool {
+        loop {
+            match self.commands.try_recv() {
+                Ok(EngineCommand::Start(config)) => self.start_outputs(*config),
+                Ok(EngineCommand::UpdateConfig(config)) => {
+                    let mut config = *config;
+                    if let Some(ref reader) = self.reader {
+                        config.resolve_devices(reader.devices());
+                    }
+                    if let Some(ref mut pad) = self.pad {
+                        pad.update_config(config, &self.events);
+                    }
+                }
+                Ok(EngineCommand::SetExtraPads(configs)) => self.set_extra_pads(configs),
+                Ok(EngineCommand::Stop) => self.stop_outputs(),
+                Ok(EngineCommand::ForwardInput(forward)) => self.forward_input = forward,
+                Ok(EngineCommand::ResyncShifter) => {
+                    for pad in self.pad.iter_mut().chain(self.extra_pads.iter_mut()) {
+                        pad.shifter.resync(&pad.config.h_shifter);
+                    }
+                }
+                Ok(EngineCommand::EmergencyStop(stopped)) => {
+                    self.emergency_stopped = stopped;
+                    for pad in self.pad.iter_mut().chain(self.extra_pads.iter_mut()) {
+                        pad.guard.set_stopped(stopped);
+                    }
+                }
+                Ok(EngineCommand::RunTest(effect, strength)) => {
+                    if let Some(ref mut pad) = self.pad {
+                        log::info!("Running force feedback test: {}", effect.name());
+                        pad.test = Some(TestRun::new(effect, strength, Instant::now()));
+                    }
+                }
+                Ok(EngineCommand::StopTest) => {
+                    if let Some(ref mut pad) = self.pad {
+                        pad.test = None;
+                    }
+                }
+                Ok(EngineCommand::StartRecording) => {
+                    match self.pad {
+                        Some(ref mut pad) => {
+                            log::info!("Recording rumble");
+                            pad.recorder = Some(RumbleRecorder::new(&pad.config.rumble, Instant::now()));
+                        }
+                        None => status(&self.events, "Start the gamepad before recording".to_string()),
+                    }
+                }
+                Ok(EngineCommand::StopRecording) => {
+                    if let Some(ref mut pad) = self.pad {
+                        pad.finish_recording(&self.events);
+                    }
+                }
+                Ok(EngineCommand::Replay(recording, speed, gain)) => {
+                    if let Some(ref mut pad) = self.pad {
+                        log::info!("Replaying rumble recording from {}", recording.created);
+                        pad.replay = Some(RumbleReplay::new(*recording, speed, gain));
+                    }
+                }
+                Ok(EngineCommand::AdjustReplay(speed, gain)) => {
+                    if let Some(replay) = self.pad.as_mut().and_then(|pad| pad.replay.as_mut()) {
+                        replay.adjust(speed, gain);
+                    }
+                }
+                Ok(EngineCommand::StopReplay) => {
+                    if let Some(ref mut pad) = self.pad {
+                        pad.replay = None;
+                    }
+                }
+                Ok(EngineCommand::SetTickRate(rate)) => self.tick = tick_duration(rate),
+                Ok(EngineCommand::Shutdown) | Err(TryRecvError::Disconnected) => return false,
+                Err(TryRecvError::Empty) => return true,
+            }
+        }
+    }
+
+    fn start_outputs(&mut self, mut config: WheelConfig) {
+        self.stop_outputs();
+        if let Some(ref reader) = self.reader {
+            config.resolve_devices(reader.devices());
+        }
+
+        let force_feedback = open_force_feedback(&config, &[]);
+        let effects = force_feedback.as_ref().map(|ff| ff.supported_effects()).unwrap_or_default();
+        let _ = self.events.send(EngineEvent::ForceFeedbackEffects(effects));
+
+        self.pad = Some(Pad::new(config, force_feedback, self.emergency_stopped, &self.events));
+    }
+
+    /// Run a pad for each profile, keeping the ones already running so a game
+    /// doesn't see them disconnect
+    fn set_extra_pads(&mut self, profiles: Vec<(String, WheelConfig)>) {
+        if self.pad.is_none() {
+            self.extra_pads.clear();
+            return;
+        }
+
+        // Pads that stopped go first, so their wheels are free for the new ones
+        self.extra_pads.retain(|pad| profiles.iter().any(|(name, _)| pad.profile.as_ref() == Some(name)));
+        // A wheel can only take orders from one pad, the first to open it keeps it
+        let mut taken: Vec<PathBuf> = self.pad.iter().chain(self.extra_pads.iter()).filter_map(Pad::ff_path).collect();
+
+        let mut extra_pads = Vec::new();
+        for (name, mut config) in profiles {
+            if let Some(ref reader) = self.reader {
+                config.resolve_devices(reader.devices());
+            }
+            if let Some(index) = self.extra_pads.iter().position(|pad| pad.profile.as_ref() == Some(&name)) {
+                let mut pad = self.extra_pads.remove(index);
+                pad.update_config(config, &self.events);
+                extra_pads.push(pad);
+                continue;
+            }
+
+            // Without a wheel of its own (a handbrake box say) the pad's rumble isn't felt
+            let force_feedback = match config.force_feedback_device {
+                Some(_) => open_force_feedback(&config, &taken),
+                None => None,
+            };
+            let mut pad = Pad::new(config, force_feedback, self.emergency_stopped, &self.events);
+            taken.extend(pad.ff_path());
+            log::info!("Started extra gamepad for {}", name);
+            pad.profile = Some(name);
+            extra_pads.push(pad);
+        }
+        self.extra_pads = extra_pads;
+    }
+
+    fn stop_outputs(&mut self) {
+        if let Some(ref mut pad) = self.pad {
+            pad.finish_recording(&self.events);
+        }
+        self.extra_pads.clear();
+        self.pad = None;
+    }
+
+    fn tick(&mut self) {
+        let Some(ref mut reader) = self.reader else {
+            return;
+        };
+
+        let events = reader.poll();
+
+        let devices_changed = events.iter().any(|e| matches!(e,
+            InputEvent::DeviceConnected { .. } | InputEvent::DeviceDisconnected { .. }));
+        if devices_changed {
+            for pad in self.pad.iter_mut().chain(self.extra_pads.iter_mut()) {
+                pad.config.resolve_devices(reader.devices());
+            }
+            let _ = self.events.send(EngineEvent::Devices(reader.devices().clone()));
+        }
+        if self.forward_input {
+            for event in events {
+                let _ = self.events.send(EngineEvent::Input(event));
+            }
+        }
+
+        let now = Instant::now();
+        for pad in self.pad.iter_mut().chain(self.extra_pads.iter_mut()) {
+            pad.tick(reader.state(), now);
+        }
+        // A stop button on any one profile stops every wheel
+        let toggled = self.pad.iter().chain(self.extra_pads.iter())
+            .find(|pad| pad.guard.is_stopped() != self.emergency_stopped)
+            .map(|pad| pad.guard.is_stopped());
+        if let Some(stopped) = toggled {
+            self.emergency_stopped = stopped;
+            for pad in self.pad.iter_mut().chain(self.extra_pads.iter_mut()) {
+                pad.guard.set_stopped(stopped);
+            }
+        }
+
+        self.ticks_since_snapshot += 1;
+        self.send_snapshot();
+    }
+
+    fn send_snapshot(&mut self) {
+        let elapsed = self.last_snapshot.elapsed();
+        if elapsed < SNAPSHOT_INTERVAL {
+            return;
+        }
+        self.measured_rate = self.ticks_since_snapshot as f32 / elapsed.as_secs_f32();
+        self.ticks_since_snapshot = 0;
+        self.last_snapshot = Instant::now();
+
+        let input = self.reader.as_ref().map(|r| r.state().clone()).unwrap_or_default();
+        let mut snapshot = match self.pad {
+            Some(ref pad) => pad.snapshot(input),
+            None => EngineSnapshot {
+                input,
+                ff_stopped: self.emergency_stopped,
+                ..Default::default()
+            },
+        };
+        snapshot.extra_pads = self.extra_pads.iter().map(Pad::status).collect();
+        snapshot.tick_rate = self.measured_rate;
+        // Full means the GUI hasn't picked up the last one yet, it'll get the next
+        let _ = self.snapshots.try_send(snapshot);
+    }
+}
+
+/// One virtual gamepad, the profile whose bindings feed it and the wheel its rumble goes back to
+struct Pad {
+    /// Profile an extra pad runs, so it's kept while that profile stays ticked
+    profile: Option<String>,
+    config: WheelConfig,
+    virtual_controller: Option<Box<dyn VirtualController>>,
+    /// Only exists while some binding goes to a key or the mouse
+    keyboard_mouse: Option<Box<dyn VirtualKeyboardMouse>>,
+    force_feedback: Option<Box<dyn ForceFeedback>>,
+    shifter: HShifter,
+    synth: EffectSynth,
+    rumble: RumbleTranslator,
+    last_rumble: RumbleState,
+    guard: TorqueGuard,
+    watchdog_tripped: bool,
+    applied_torque: f32,
+    /// Whether passed through effects have been stopped for the emergency stop
+    passthrough_stopped: bool,
+    test: Option<TestRun>,
+    recorder: Option<RumbleRecorder>,
+    replay: Option<RumbleReplay>,
+    output: XboxControllerState,
+}
+
+impl Pad {
+    /// `config` should already have its devices resolved
+    fn new(
+        config: WheelConfig,
+        force_feedback: Option<Box<dyn ForceFeedback>>,
+        emergency_stopped: bool,
+        events: &Sender<EngineEvent>,
+    ) -> Self {
+        let mut guard = TorqueGuard::default();
+        guard.set_stopped(emergency_stopped);
+        let shifter = HShifter::new(&config.h_shifter);
+        let mut pad = Self {
+            profile: None,
+            config,
+            virtual_controller: None,
+            keyboard_mouse: None,
+            force_feedback,
+            shifter,
+            synth: EffectSynth::default(),
+            rumble: RumbleTranslator::default(),
+            last_rumble: RumbleState::default(),
+            guard,
+            watchdog_tripped: false,
+            applied_torque: 0.0,
+            passthrough_stopped: false,
+            test: None,
+            recorder: None,
+            replay: None,
+            output: XboxControllerState::default(),
+        };
+        pad.create_controller(events);
+        pad.update_keyboard_mouse(events);
+        pad
+    }
+
+    /// Swap in an edited config without recreating the outputs it doesn't change
+    fn update_config(&mut self, config: WheelConfig, events: &Sender<EngineEvent>) {
+        let old = std::mem::replace(&mut self.config, config);
+        // A new layout means a new device, uinput can't change one that's been created.
+        // Passthrough decides which effects the device advertises, so that's a new device too.
+        let layout_changed = old.controller != self.config.controller
+            || (self.config.controller == ControllerKind::Joystick && old.joystick != self.config.joystick)
+            || old.ff_passthrough != self.config.ff_passthrough;
+        if layout_changed {
+            self.create_controller(events);
+        }
+        self.update_keyboard_mouse(events);
+        // Turning the shifter on or changing where the game starts puts us back at the start
+        if old.h_shifter.enabled != self.config.h_shifter.enabled || old.h_shifter.lowest_gear != self.config.h_shifter.lowest_gear {
+            self.shifter = HShifter::new(&self.config.h_shifter);
+        }
+        if let (true, Some(ff)) = (old.force_feedback != self.config.force_feedback, self.force_feedback.as_mut()) {
+            if let Err(e) = ff.configure(&self.config.force_feedback) {
+                log::warn!("Failed to configure force feedback: {}", e);
+            }
+        }
+    }
+
+    /// (Re)create the virtual gamepad the config asks for
+    fn create_controller(&mut self, events: &Sender<EngineEvent>) {
+        // Only one pad at a time, or the game would see the old one linger
+        self.virtual_controller = None;
+        // The old pad's effects went with it, nothing will stop or erase them now
+        if let Some(ref mut ff) = self.force_feedback {
+            ff.clear_passthrough();
+        }
+        let passthrough = match self.force_feedback {
+            Some(ref ff) if self.config.ff_passthrough => ff.passthrough_effects(),
+            _ => Vec::new(),
+        };
+        match virtual_controller::create_controller(self.config.controller, &self.config.joystick, &passthrough) {
+            Ok(vc) => {
+                let message = match vc.event_node() {
+                    Some(node) => format!("{} gamepad connected as {}", self.config.controller.name(), node.display()),
+                    None => format!("{} gamepad connected", self.config.controller.name()),
+                };
+                self.virtual_controller = Some(vc);
+                status(events, message);
+            }
+            Err(e) => {
+                let message = format!("Failed to create gamepad: {}", e);
+                log::error!("{}", message);
+                status(events, message);
+            }
+        }
+    }
+
+    /// Create or drop the keyboard and mouse to match the bindings
+    fn update_keyboard_mouse(&mut self, events: &Sender<EngineEvent>) {
+        if !self.config.uses_keyboard_mouse() {
+            self.keyboard_mouse = None;
+            return;
+        }
+        if self.keyboard_mouse.is_some() {
+            return;
+        }
+        match virtual_controller::create_keyboard_mouse() {
+            Ok(keyboard_mouse) => self.keyboard_mouse = Some(keyboard_mouse),
+            Err(e) => {
+                let message = format!("Failed to create keyboard and mouse: {}", e);
+                log::error!("{}", message);
+                status(events, message);
+            }
+        }
+    }
+
+    fn finish_recording(&mut self, events: &Sender<EngineEvent>) {
+        let Some(recorder) = self.recorder.take() else {
+            return;
+        };
+        let recording = recorder.finish(Instant::now());
+        log::info!("Recorded {} rumble samples", recording.samples.len());
+        let _ = events.send(EngineEvent::Recording(Box::new(recording)));
+    }
+
+    fn tick(&mut self, input: &InputState, now: Instant) {
+        let config = &self.config;
+        let native_vibration = config.rumble.native_vibration
+            && self.force_feedback.as_ref().is_some_and(|ff| ff.supports_vibration());
+        let mut xbox_state = mapping::apply_bindings(&config.bindings, input);
+
+        if config.h_shifter.enabled {
+            let role = match self.shifter.update(&config.h_shifter, input, now) {
+                Some(ShiftOutput::Up) => Some(BindingRole::ShiftUp),
+                Some(ShiftOutput::Down) => Some(BindingRole::ShiftDown),
+                None => None,
+            };
+            if let Some(binding) = role.and_then(|role| config.binding(role)) {
+                mapping::press_target(&mut xbox_state, binding.target);
+            }
+        }
+
+        // The game's force plus the end stop when the wheel is past the soft lock and road feel
+        let mut torque = config.soft_lock_torque(input);
+        if let Some(position) = config.steering_position(input) {
+            torque += self.synth.update(&config.effects, position, now);
+        }
+
+        let mut passthrough = Vec::new();
+        let mut rumble = None;
+        if let Some(ref mut vc) = self.virtual_controller {
+            passthrough = vc.take_passthrough();
+            if let Err(e) = vc.update(&xbox_state) {
+                log::error!("Failed to update virtual controller: {}", e);
+            }
+
+            if let Ok(game_rumble) = vc.get_rumble() {
+                if game_rumble.large_motor > 0.01 || game_rumble.small_motor > 0.01 {
+                    log::debug!("Rumble from game: large={:.2}, small={:.2}",
+                               game_rumble.large_motor, game_rumble.small_motor);
+                }
+                if let Some(ref mut recorder) = self.recorder {
+                    recorder.push(&game_rumble, now);
+                }
+                rumble = Some(game_rumble);
+            }
+        }
+
+        if let Some(ref mut keyboard_mouse) = self.keyboard_mouse {
+            if let Err(e) = keyboard_mouse.update(&xbox_state.keyboard_mouse, &config.keyboard_mouse, now) {
+                log::error!("Failed to update virtual keyboard and mouse: {}", e);
+            }
+        }
+
+        // A replay stands in for the game, so it goes through the same translation
+        if let Some(ref mut replay) = self.replay {
+            match replay.state(now) {
+                Some(replayed) => rumble = Some(replayed),
+                None => {
+                    log::info!("Rumble replay finished");
+                    self.replay = None;
+                }
+            }
+        }
+
+        if let Some(rumble) = rumble {
+            // A game that stopped talking (or a dead gamepad thread) mustn't leave its last force playing
+            let scale = config.safety.watchdog_scale(rumble.last_heard(now), now);
+            self.watchdog_tripped = scale < 1.0 && (rumble.large_motor > 0.0 || rumble.small_motor > 0.0 || rumble.force != 0.0);
+            torque += scale * self.rumble.update(&config.rumble, &rumble, self.synth.velocity(), now, native_vibration);
+            self.last_rumble = rumble;
+        }
+
+        // A test replaces everything else so it's all that's felt: the game's force and
+        // road feel here, its vibration and passed through effects below
+        if let Some(test) = self.test {
+            match test.torque(config.steering_position(input), now) {
+                Some(test_torque) => torque = test_torque,
+                None => self.test = None,
+            }
+        }
+
+        let testing = self.test.is_some();
+        let torque = self.guard.update(&config.safety, input, torque.clamp(-1.0, 1.0), !testing, now);
+        self.applied_torque = torque;
+        if let Some(ref mut ff) = self.force_feedback {
+            if let Err(e) = ff.apply_torque(torque) {
+                log::error!("Failed to apply force feedback: {}", e);
+            }
+            // Still called with native vibration off so a playing effect gets stopped
+            if ff.supports_vibration() {
+                let (magnitude, period) = self.rumble.vibration();
+                let scale = config.safety.watchdog_scale(self.last_rumble.last_heard(now), now);
+                let magnitude = if self.guard.is_stopped() || testing { 0.0 } else { magnitude * scale };
+                if let Err(e) = ff.apply_vibration(magnitude, period, config.rumble.waveform) {
+                    log::error!("Failed to apply vibration: {}", e);
+                }
+            }
+
+            // The game's own effects can't be slew limited, the stop just holds them off.
+            // So does a test, they don't restart afterwards until the game plays them again.
+            let stopped = self.guard.is_stopped() || testing;
+            if stopped && !self.passthrough_stopped {
+                if let Err(e) = ff.stop_passthrough() {
+                    log::error!("Failed to stop passed through effects: {}", e);
+                }
+            }
+            self.passthrough_stopped = stopped;
+            for event in passthrough {
+                if stopped && matches!(event, FfPassthrough::Play { count: 1.., .. }) {
+                    continue;
+                }
+                if let Err(e) = ff.passthrough(&event) {
+                    log::error!("Failed to pass effect through: {}", e);
+                }
+            }
+        }
+
+        self.output = xbox_state;
+    }
+
+    fn snapshot(&self, input: InputState) -> EngineSnapshot {
+        EngineSnapshot {
+            output: self.output.clone(),
+            input,
+            gamepad_connected: self.virtual_controller.as_ref().map(|vc| vc.is_connected()).unwrap_or(false),
+            gamepad_node: self.gamepad_node(),
+            ff_available: self.force_feedback.as_ref().map(|ff| ff.is_available()).unwrap_or(false),
+            gear: self.shifter.current_gear(),
+            resyncing: self.shifter.is_resyncing(),
+            effect_forces: self.synth.forces(),
+            rumble: self.last_rumble.clone(),
+            rumble_torque: self.rumble.torque(),
+            rumble_vibration: self.rumble.vibration().0,
+            ff_stopped: self.guard.is_stopped(),
+            watchdog_tripped: self.watchdog_tripped,
+            torque: self.applied_torque,
+            test: self.test.map(|test| test.effect()),
+            recording: self.recorder.as_ref().map(|r| r.elapsed(Instant::now())),
+            replay: self.replay.as_ref().map(|r| r.progress()),
+            ff_vibration: self.force_feedback.as_ref().map(|ff| ff.supports_vibration()).unwrap_or(false),
+            ..Default::default()
+        }
+    }
+
+    fn ff_path(&self) -> Option<PathBuf> {
+        self.force_feedback.as_ref().and_then(|ff| ff.device_path()).map(PathBuf::from)
+    }
+
+    fn gamepad_node(&self) -> Option<PathBuf> {
+        self.virtual_controller.as_ref().and_then(|vc| vc.event_node()).map(PathBuf::from)
+    }
+
+    fn status(&self) -> PadStatus {
+        PadStatus {
+            gamepad_connected: self.virtual_controller.as_ref().map(|vc| vc.is_connected()).unwrap_or(false),
+            gamepad_node: self.gamepad_node(),
+            ff_available: self.force_feedback.as_ref().map(|ff| ff.is_available()).unwrap_or(false),
+            gear: self.shifter.current_gear(),
+            torque: self.applied_torque,
+        }
+    }
+}
+
+/// Open and configure the wheel `config` drives, passing over the `taken` devices other
+/// pads drive. None if there's no free force feedback device.
+fn open_force_feedback(config: &WheelConfig, taken: &[PathBuf]) -> Option<Box<dyn ForceFeedback>> {
+    match ForceFeedbackDevice::new(config.force_feedback_device.as_ref(), taken) {
+        Ok(mut ff) => {
+            if !ff.is_available() {
+                return None;
+            }
+            if let Err(e) = ff.configure(&config.force_feedback) {
+                log::warn!("Failed to configure force feedback: {}", e);
+            }
+            log::info!("Force feedback initialized");
+            Some(Box::new(ff))
+        }
+        Err(e) => {
+            log::warn!("Force feedback not available: {}", e);
+            None
+        }
+    }
+}
+
+fn status(events: &Sender<EngineEvent>, message: String) {
+    let _ = events.send(EngineEvent::Status(message));
+}
+
+fn tick_duration(rate: u32) -> Duration {
+    Duration::from_secs_f64(1.0 / rate.clamp(MIN_TICK_RATE, MAX_TICK_RATE) as f64)
+}
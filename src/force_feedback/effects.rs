@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Rate the synthesized effects are simulated at, independent of how often they're polled
+pub const SYNTH_TICK: Duration = Duration::from_millis(2);
+
+/// Steering position (-1..1 over the whole wheel rotation) where the spring hits full force
+const SPRING_FULL_AT: f32 = 0.25;
+/// Steering speed in position units per second where the damper hits full force
+const DAMPER_FULL_AT: f32 = 2.0;
+/// Below this speed friction fades out instead of snapping between directions
+const FRICTION_VELOCITY: f32 = 0.05;
+/// Steering acceleration in position units per second² where inertia hits full force
+const INERTIA_FULL_AT: f32 = 20.0;
+/// Smoothing per tick for the velocity and acceleration estimates, the position is noisy
+const SMOOTHING: f32 = 0.1;
+/// Don't try to catch up on more than this after a stall, just start over
+const MAX_STEPS: u32 = 100;
+
+/// Strength of each synthesized effect, 0 (off) to 1
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct EffectGains {
+    /// Pulls the wheel back to center
+    pub spring: f32,
+    /// Resists turning fast
+    pub damper: f32,
+    /// Constant resistance to any movement
+    pub friction: f32,
+    /// Resists speeding up or slowing down the wheel
+    pub inertia: f32,
+}
+
+/// Torque from each effect on the last tick, for display
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EffectForces {
+    pub spring: f32,
+    pub damper: f32,
+    pub friction: f32,
+    pub inertia: f32,
+}
+
+impl EffectForces {
+    pub fn total(&self) -> f32 {
+        self.spring + self.damper + self.friction + self.inertia
+    }
+}
+
+/// Turns steering position into spring/damper/friction/inertia torque, like a game with
+/// real FF would. Runs at a fixed tick so the effects feel the same at any frame rate.
+#[derive(Debug, Default)]
+pub struct EffectSynth {
+    last: Option<(f32, Instant)>,
+    /// Time not yet simulated, less than one tick
+    remainder: Duration,
+    velocity: f32,
+    acceleration: f32,
+    forces: EffectForces,
+}
+
+impl EffectSynth {
+    pub fn forces(&self) -> EffectForces {
+        self.forces
+    }
+
+    /// Smoothed steering speed in position units per second
+    pub fn velocity(&self) -> f32 {
+        self.velocity
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Advance to `now` with the wheel at `position` and return the torque to apply
+    pub fn update(&mut self, gains: &EffectGains, position: f32, now: Instant) -> f32 {
+        let Some((last_position, last_time)) = self.last else {
+            self.last = Some((position, now));
+            return 0.0;
+        };
+
+        let elapsed = now.saturating_duration_since(last_time) + self.remainder;
+        let steps = (elapsed.as_nanos() / SYNTH_TICK.as_nanos()) as u32;
+        if steps == 0 {
+            return self.forces.total();
+        }
+        if steps > MAX_STEPS {
+            self.reset();
+            self.last = Some((position, now));
+            return 0.0;
+        }
+        self.remainder = elapsed - SYNTH_TICK * steps;
+        self.last = Some((position, now));
+
+        // Spread the movement since the last sample evenly over the ticks that passed
+        let dt = SYNTH_TICK.as_secs_f32();
+        let step_velocity = (position - last_position) / (dt * steps as f32);
+        for _ in 0..steps {
+            let velocity = self.velocity + (step_velocity - self.velocity) * SMOOTHING;
+            let acceleration = (velocity - self.velocity) / dt;
+            self.acceleration += (acceleration - self.acceleration) * SMOOTHING;
+            self.velocity = velocity;
+        }
+
+        self.forces = EffectForces {
+            spring: -gains.spring * (position / SPRING_FULL_AT).clamp(-1.0, 1.0),
+            damper: -gains.damper * (self.velocity / DAMPER_FULL_AT).clamp(-1.0, 1.0),
+            friction: -gains.friction * (self.velocity / FRICTION_VELOCITY).tanh(),
+            inertia: -gains.inertia * (self.acceleration / INERTIA_FULL_AT).clamp(-1.0, 1.0),
+        };
+        self.forces.total()
+    }
+}
@@ -1,32 +1,126 @@
-use super::{ForceFeedback, RumbleState};
-use evdev::{Device, FFEffect, FFEffectData, FFEffectKind, FFReplay, FFTrigger, FFEnvelope};
-use std::path::Path;
+use super::{DeviceSettings, ForceFeedback, VibrationWaveform};
+use crate::input::DeviceIdentity;
+use crate::virtual_controller::FfPassthrough;
+use evdev::{Device, EventType, FFEffect, FFEffectCode, FFEffectData, FFEffectKind, FFReplay, FFTrigger, FFEnvelope, FFWaveform, InputEvent};
+use std::collections::HashMap;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Vibration weaker than this stops the periodic effect instead of playing it at nothing
+const VIBRATION_THRESHOLD: f32 = 0.01;
+
+// evdev ioctls for uploading and erasing raw effects (x86_64)
+const EVIOCSFF: libc::c_ulong = 0x40304580;
+const EVIOCRMFF: libc::c_ulong = 0x40044581;
+
+/// The kernel's struct ff_effect, so passed through effects go up byte for byte.
+/// evdev's own types drop the damper's coefficients.
+#[repr(C, align(8))]
+struct KernelEffect {
+    type_: u16,
+    id: i16,
+    direction: u16,
+    trigger: [u8; 4],
+    replay: [u8; 4],
+    _pad: [u8; 2],
+    params: [u8; 32],
+}
 
 pub struct ForceFeedbackDevice {
     device: Option<Device>,
     available: bool,
     constant_effect: Option<FFEffect>,
     effect_playing: bool,
+    /// Sine/square effect for the small motor, uploaded the first time it's needed
+    periodic_effect: Option<FFEffect>,
+    periodic_playing: bool,
+    /// Last (magnitude, period ms, waveform) sent, re-uploading restarts the wave on some wheels
+    periodic_params: Option<(i16, u16, VibrationWaveform)>,
+    supports_periodic: bool,
+    /// Autocenter to put back on drop, set once we've changed the wheel's
+    exit_autocenter: Option<u16>,
+    /// Where `device` was opened from
+    path: Option<PathBuf>,
+    /// Whether we've changed the wheel's gain, so it goes back to full on drop
+    gain_changed: bool,
+    /// Game effect id to wheel effect id for passed through effects
+    passthrough: HashMap<i16, i16>,
+    /// Our gain and the game's FF_GAIN, the wheel gets both multiplied
+    gain: f32,
+    game_gain: f32,
 }
 
 impl ForceFeedbackDevice {
-    pub fn new(device_path: Option<&str>) -> anyhow::Result<Self> {
+    /// Open the FF device matching `target`, or the first FF capable device if there's no target.
+    /// Devices at `taken` paths are driven by another gamepad and never opened.
+    pub fn new(target: Option<&DeviceIdentity>, taken: &[PathBuf]) -> anyhow::Result<Self> {
         let mut ff_device = Self {
             device: None,
             available: false,
             constant_effect: None,
             effect_playing: false,
+            periodic_effect: None,
+            periodic_playing: false,
+            periodic_params: None,
+            supports_periodic: false,
+            exit_autocenter: None,
+            gain_changed: false,
+            path: None,
+            passthrough: HashMap::new(),
+            gain: 1.0,
+            game_gain: 1.0,
         };
 
-        if let Some(path) = device_path {
-            ff_device.connect(path)?;
+        if let Some(target) = target {
+            match Self::find_device(target, taken) {
+                Some(path) => ff_device.connect(&path)?,
+                None => log::warn!("Force feedback device {} is not connected", target.name),
+            }
         } else {
-            ff_device.auto_detect()?;
+            ff_device.auto_detect(taken)?;
         }
 
         Ok(ff_device)
     }
 
+    /// Find the event node of the free FF capable device that best matches `target`
+    fn find_device(target: &DeviceIdentity, taken: &[PathBuf]) -> Option<String> {
+        let mut best: Option<(u32, String)> = None;
+
+        for i in 0..32 {
+            let path = format!("/dev/input/event{}", i);
+            if !Path::new(&path).exists() || is_taken(&path, taken) {
+                continue;
+            }
+            let Ok(device) = Device::open(&path) else {
+                continue;
+            };
+            if !device.supported_ff().map(|ff| ff.iter().count() > 0).unwrap_or(false) {
+                continue;
+            }
+
+            let input_id = device.input_id();
+            let identity = DeviceIdentity {
+                name: device.name().unwrap_or_default().to_string(),
+                vendor_id: Some(input_id.vendor()),
+                product_id: Some(input_id.product()),
+                serial: None,
+                uniq: device.unique_name().map(|s| s.to_string()),
+                phys: device.physical_path().map(|s| s.to_string()),
+            };
+
+            if let Some(score) = target.match_score(&identity) {
+                log::debug!("FF candidate {} ({}) scored {}", identity.name, path, score);
+                if best.as_ref().map(|(best_score, _)| score > *best_score).unwrap_or(true) {
+                    best = Some((score, path));
+                }
+            }
+        }
+
+        best.map(|(_, path)| path)
+    }
+
     fn connect(&mut self, path: &str) -> anyhow::Result<()> {
         let device = Device::open(path)
             .map_err(|e| anyhow::anyhow!("Failed to open device {}: {}", path, e))?;
@@ -43,7 +137,9 @@ impl ForceFeedbackDevice {
         }
 
         log::info!("Connected to force feedback device: {} at {}", device.name().unwrap_or("Unknown"), path);
+        self.supports_periodic = Self::has_periodic(&device);
         self.device = Some(device);
+        self.path = Some(PathBuf::from(path));
         self.available = true;
 
         self.create_constant_effect()?;
@@ -86,20 +182,92 @@ impl ForceFeedbackDevice {
         }
     }
 
-    fn auto_detect(&mut self) -> anyhow::Result<()> {
+    fn has_periodic(device: &Device) -> bool {
+        device.supported_ff().map(|ff| {
+            ff.contains(FFEffectCode::FF_PERIODIC)
+                && ff.contains(FFEffectCode::FF_SINE)
+                && ff.contains(FFEffectCode::FF_SQUARE)
+        }).unwrap_or(false)
+    }
+
+    fn periodic_effect_data(magnitude: i16, period_ms: u16, waveform: VibrationWaveform) -> FFEffectData {
+        FFEffectData {
+            direction: 16384, // East (0x4000), the wave swings both ways from here
+            trigger: FFTrigger::default(),
+            replay: FFReplay {
+                length: 0, // Infinite duration
+                delay: 0,
+            },
+            kind: FFEffectKind::Periodic {
+                waveform: match waveform {
+                    VibrationWaveform::Sine => FFWaveform::Sine,
+                    VibrationWaveform::Square => FFWaveform::Square,
+                },
+                period: period_ms,
+                magnitude,
+                offset: 0,
+                phase: 0,
+                envelope: FFEnvelope {
+                    attack_length: 0,
+                    attack_level: 0,
+                    fade_length: 0,
+                    fade_level: 0,
+                },
+            },
+        }
+    }
+
+    fn stop_periodic(&mut self) -> anyhow::Result<()> {
+        if let Some(ref mut effect) = self.periodic_effect {
+            if self.periodic_playing {
+                effect.stop()
+                    .map_err(|e| anyhow::anyhow!("Failed to stop periodic effect: {}", e))?;
+            }
+        }
+        self.periodic_playing = false;
+        Ok(())
+    }
+
+    fn apply_gain(&mut self) -> anyhow::Result<()> {
+        let Some(ref mut device) = self.device else {
+            return Ok(());
+        };
+        if device.supported_ff().map(|ff| ff.contains(FFEffectCode::FF_GAIN)).unwrap_or(false) {
+            device.set_ff_gain(ff_level(self.gain * self.game_gain))
+                .map_err(|e| anyhow::anyhow!("Failed to set force feedback gain: {}", e))?;
+            self.gain_changed = true;
+        }
+        Ok(())
+    }
+
+    fn erase_passthrough(&mut self) {
+        let Some(ref device) = self.device else {
+            return;
+        };
+        for (_, wheel_id) in self.passthrough.drain() {
+            if unsafe { libc::ioctl(device.as_raw_fd(), EVIOCRMFF, wheel_id as libc::c_int) } < 0 {
+                log::warn!("Failed to erase passed through effect {}: {}", wheel_id, std::io::Error::last_os_error());
+            }
+        }
+    }
+
+    fn auto_detect(&mut self, taken: &[PathBuf]) -> anyhow::Result<()> {
         for i in 0..32 {
             let path = format!("/dev/input/event{}", i);
-            if Path::new(&path).exists() {
+            if Path::new(&path).exists() && !is_taken(&path, taken) {
                 if let Ok(device) = Device::open(&path) {
                     if device.supported_ff().map(|ff| ff.iter().count() > 0).unwrap_or(false) {
                         log::info!("Auto-detected FF device: {} at {}",
                             device.name().unwrap_or("Unknown"), path);
+                        self.supports_periodic = Self::has_periodic(&device);
                         self.device = Some(device);
+                        self.path = Some(PathBuf::from(&path));
                         self.available = true;
 
                         if let Err(e) = self.create_constant_effect() {
                             log::warn!("Failed to create constant force effect for auto-detected device: {}", e);
                             self.device = None;
+                            self.path = None;
                             self.available = false;
                             continue;
                         }
@@ -115,8 +283,125 @@ impl ForceFeedbackDevice {
     }
 }
 
+/// Game effects that can be handed to the wheel as they are, and the periodic waveforms
+const PASSTHROUGH_EFFECTS: &[FFEffectCode] = &[
+    FFEffectCode::FF_CONSTANT, FFEffectCode::FF_PERIODIC, FFEffectCode::FF_SPRING, FFEffectCode::FF_DAMPER,
+    FFEffectCode::FF_SQUARE, FFEffectCode::FF_TRIANGLE, FFEffectCode::FF_SINE, FFEffectCode::FF_SAW_UP,
+    FFEffectCode::FF_SAW_DOWN,
+];
+
+fn is_taken(path: &str, taken: &[PathBuf]) -> bool {
+    taken.iter().any(|t| t == Path::new(path))
+}
+
+/// 0..1 to the 0..0xFFFF range FF_GAIN and FF_AUTOCENTER take
+fn ff_level(value: f32) -> u16 {
+    (value.clamp(0.0, 1.0) * u16::MAX as f32) as u16
+}
+
 impl ForceFeedback for ForceFeedbackDevice {
-    fn apply_rumble(&mut self, rumble: &RumbleState) -> anyhow::Result<()> {
+    fn configure(&mut self, settings: &DeviceSettings) -> anyhow::Result<()> {
+        let Some(ref mut device) = self.device else {
+            return Ok(());
+        };
+        let (has_gain, has_autocenter) = device.supported_ff()
+            .map(|ff| (ff.contains(FFEffectCode::FF_GAIN), ff.contains(FFEffectCode::FF_AUTOCENTER)))
+            .unwrap_or_default();
+
+        if has_autocenter {
+            device.set_ff_autocenter(ff_level(settings.autocenter))
+                .map_err(|e| anyhow::anyhow!("Failed to set autocenter: {}", e))?;
+            self.exit_autocenter = Some(ff_level(settings.exit_autocenter));
+        }
+        log::info!("Force feedback gain {:.0}%{}, autocenter {:.0}%{}",
+            settings.gain * 100.0, if has_gain { "" } else { " (unsupported)" },
+            settings.autocenter * 100.0, if has_autocenter { "" } else { " (unsupported)" });
+
+        self.gain = settings.gain;
+        self.apply_gain()
+    }
+
+    fn passthrough(&mut self, event: &FfPassthrough) -> anyhow::Result<()> {
+        let Some(ref mut device) = self.device else {
+            return Ok(());
+        };
+
+        match *event {
+            FfPassthrough::Upload { id, effect } => {
+                let supported = device.supported_ff()
+                    .map(|ff| ff.contains(FFEffectCode(effect.kind)))
+                    .unwrap_or(false);
+                if !supported {
+                    log::warn!("Wheel can't play passed through effect type {:#x}", effect.kind);
+                    return Ok(());
+                }
+
+                let mut kernel = KernelEffect {
+                    type_: effect.kind,
+                    // -1 uploads a new effect, an existing id updates it in place
+                    id: self.passthrough.get(&id).copied().unwrap_or(-1),
+                    direction: effect.direction,
+                    trigger: effect.trigger,
+                    replay: effect.replay,
+                    _pad: [0; 2],
+                    params: effect.params,
+                };
+                if unsafe { libc::ioctl(device.as_raw_fd(), EVIOCSFF, &mut kernel as *mut KernelEffect) } < 0 {
+                    return Err(anyhow::anyhow!("Failed to upload passed through effect: {}",
+                        std::io::Error::last_os_error()));
+                }
+                log::debug!("Passed through effect {} as wheel effect {}", id, kernel.id);
+                self.passthrough.insert(id, kernel.id);
+            }
+            FfPassthrough::Erase(id) => {
+                if let Some(wheel_id) = self.passthrough.remove(&id) {
+                    if unsafe { libc::ioctl(device.as_raw_fd(), EVIOCRMFF, wheel_id as libc::c_int) } < 0 {
+                        return Err(anyhow::anyhow!("Failed to erase passed through effect: {}",
+                            std::io::Error::last_os_error()));
+                    }
+                }
+            }
+            FfPassthrough::Play { id, count } => {
+                if let Some(&wheel_id) = self.passthrough.get(&id) {
+                    device.send_events(&[InputEvent::new(EventType::FORCEFEEDBACK.0, wheel_id as u16, count)])
+                        .map_err(|e| anyhow::anyhow!("Failed to play passed through effect: {}", e))?;
+                }
+            }
+            FfPassthrough::Gain(gain) => {
+                self.game_gain = gain as f32 / u16::MAX as f32;
+                self.apply_gain()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn stop_passthrough(&mut self) -> anyhow::Result<()> {
+        let Some(ref mut device) = self.device else {
+            return Ok(());
+        };
+        let events: Vec<_> = self.passthrough.values()
+            .map(|&wheel_id| InputEvent::new(EventType::FORCEFEEDBACK.0, wheel_id as u16, 0))
+            .collect();
+        device.send_events(&events)
+            .map_err(|e| anyhow::anyhow!("Failed to stop passed through effects: {}", e))
+    }
+
+    fn device_path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    fn clear_passthrough(&mut self) {
+        self.erase_passthrough();
+    }
+
+    fn passthrough_effects(&self) -> Vec<u16> {
+        let Some(ff) = self.device.as_ref().and_then(|device| device.supported_ff()) else {
+            return Vec::new();
+        };
+        PASSTHROUGH_EFFECTS.iter().filter(|code| ff.contains(**code)).map(|code| code.0).collect()
+    }
+
+    fn apply_torque(&mut self, torque: f32) -> anyhow::Result<()> {
         if !self.available {
             return Ok(());
         }
@@ -125,9 +410,7 @@ impl ForceFeedback for ForceFeedbackDevice {
             self.create_constant_effect()?;
         }
 
-        let large_clamped = rumble.large_motor.clamp(0.0, 1.0);
-        let small_clamped = rumble.small_motor.clamp(0.0, 1.0);
-        let force_level = ((large_clamped - small_clamped) * 32767.0) as i16;
+        let force_level = (torque.clamp(-1.0, 1.0) * 32767.0) as i16;
 
         if let Some(ref mut effect) = self.constant_effect {
             let effect_data = FFEffectData {
@@ -162,11 +445,63 @@ impl ForceFeedback for ForceFeedbackDevice {
         Ok(())
     }
 
+    fn apply_vibration(&mut self, magnitude: f32, period: Duration, waveform: VibrationWaveform) -> anyhow::Result<()> {
+        if !self.available || !self.supports_periodic {
+            return Ok(());
+        }
+        if magnitude < VIBRATION_THRESHOLD {
+            return self.stop_periodic();
+        }
+
+        let level = (magnitude.clamp(0.0, 1.0) * 32767.0) as i16;
+        let period_ms = period.as_millis().clamp(1, u16::MAX as u128) as u16;
+        let params = (level, period_ms, waveform);
+        let effect_data = Self::periodic_effect_data(level, period_ms, waveform);
+
+        match self.periodic_effect {
+            Some(ref mut effect) => {
+                if self.periodic_params != Some(params) {
+                    effect.update(effect_data)
+                        .map_err(|e| anyhow::anyhow!("Failed to update periodic effect: {}", e))?;
+                }
+            }
+            None => {
+                let device = self.device.as_mut()
+                    .ok_or_else(|| anyhow::anyhow!("No device available"))?;
+                let effect = device.upload_ff_effect(effect_data)
+                    .map_err(|e| anyhow::anyhow!("Failed to create periodic effect: {}", e))?;
+                log::info!("Created periodic effect with ID: {}", effect.id());
+                self.periodic_effect = Some(effect);
+            }
+        }
+        self.periodic_params = Some(params);
+
+        if !self.periodic_playing {
+            if let Some(ref mut effect) = self.periodic_effect {
+                effect.play(1)
+                    .map_err(|e| anyhow::anyhow!("Failed to play periodic effect: {}", e))?;
+            }
+            self.periodic_playing = true;
+        }
+
+        Ok(())
+    }
+
+    fn supports_vibration(&self) -> bool {
+        self.available && self.supports_periodic
+    }
+
     fn stop(&mut self) -> anyhow::Result<()> {
         if !self.available {
             return Ok(());
         }
 
+        self.stop_periodic()?;
+        self.erase_passthrough();
+        // Dropping the effect erases it from the device
+        self.periodic_effect = None;
+        self.periodic_params = None;
+
         // Stop and drop the effect if it exists
         if let Some(mut effect) = self.constant_effect.take() {
             effect.stop()
@@ -180,6 +515,13 @@ impl ForceFeedback for ForceFeedbackDevice {
     fn is_available(&self) -> bool {
         self.available
     }
+
+    fn supported_effects(&self) -> Vec<String> {
+        self.device.as_ref()
+            .and_then(|device| device.supported_ff())
+            .map(|ff| ff.iter().map(|code| format!("{:?}", code)).collect())
+            .unwrap_or_default()
+    }
 }
 
 impl Drop for ForceFeedbackDevice {
@@ -187,5 +529,21 @@ impl Drop for ForceFeedbackDevice {
         if let Err(e) = self.stop() {
             log::warn!("Failed to clean up force feedback on drop: {}", e);
         }
+
+        // evdev can't read either setting back, so only what we changed goes back to the
+        // driver's default: full gain, and the centering spring as configured (full unless told)
+        let Some(ref mut device) = self.device else {
+            return;
+        };
+        if self.gain_changed {
+            if let Err(e) = device.set_ff_gain(u16::MAX) {
+                log::warn!("Failed to restore gain: {}", e);
+            }
+        }
+        if let Some(autocenter) = self.exit_autocenter {
+            if let Err(e) = device.set_ff_autocenter(autocenter) {
+                log::warn!("Failed to restore autocenter: {}", e);
+            }
+        }
     }
 }
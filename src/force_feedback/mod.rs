@@ -1,3 +1,8 @@
+mod effects;
+mod recording;
+mod rumble;
+mod safety;
+mod test_bench;
 #[cfg(target_os = "linux")]
 mod linux;
 #[cfg(windows)]
@@ -10,12 +15,77 @@ pub use linux::ForceFeedbackDevice;
 #[cfg(windows)]
 pub use windows::ForceFeedbackDevice;
 
-use super::virtual_controller::RumbleState;
+use crate::virtual_controller::FfPassthrough;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+pub use effects::{EffectForces, EffectGains, EffectSynth};
+pub use recording::{list_recordings, RumbleRecorder, RumbleRecording, RumbleReplay, MAX_REPLAY_SPEED, MIN_REPLAY_SPEED};
+pub use rumble::{RumbleMode, RumbleTranslation, RumbleTranslator, VibrationWaveform};
+pub use safety::{SafetySettings, TorqueGuard};
+pub use test_bench::{TestEffect, TestRun};
+
+/// Wheel-wide settings the driver applies on top of every effect
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceSettings {
+    /// Overall strength, 0 to 1
+    pub gain: f32,
+    /// The wheel's built-in centering spring, 0 (off) to 1. Fights RoWheel's effects when on.
+    pub autocenter: f32,
+    /// Centering spring to leave on when RoWheel closes. Linux can't read the wheel's
+    /// own setting back, so this defaults to full like the drivers do.
+    pub exit_autocenter: f32,
+}
+
+impl Default for DeviceSettings {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            autocenter: 0.0,
+            exit_autocenter: 1.0,
+        }
+    }
+}
 
 pub trait ForceFeedback: Send {
+    /// Apply gain and autocenter, called on connect and whenever they change
+    fn configure(&mut self, settings: &DeviceSettings) -> anyhow::Result<()>;
 
-    fn apply_rumble(&mut self, rumble: &RumbleState) -> anyhow::Result<()>;
+    /// Constant force on the wheel, -1.0 (full left) to 1.0 (full right)
+    fn apply_torque(&mut self, torque: f32) -> anyhow::Result<()>;
+    /// Vibration on top of the torque, `magnitude` 0.0 to 1.0. Only called if `supports_vibration` is true.
+    fn apply_vibration(&mut self, _magnitude: f32, _period: Duration, _waveform: VibrationWaveform) -> anyhow::Result<()> {
+        Ok(())
+    }
+    fn supports_vibration(&self) -> bool {
+        false
+    }
+    /// Replay the game's own effect traffic on the wheel, see `VirtualController::take_passthrough`
+    fn passthrough(&mut self, _event: &FfPassthrough) -> anyhow::Result<()> {
+        Ok(())
+    }
+    /// Stop every passed through effect, leaving them uploaded
+    fn stop_passthrough(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+    /// Erase every passed through effect, for when the gamepad they came from is gone
+    fn clear_passthrough(&mut self) {}
+    /// Which of the effect types and periodic waveforms that can be passed through
+    /// the wheel plays itself, as evdev codes
+    fn passthrough_effects(&self) -> Vec<u16> {
+        Vec::new()
+    }
     fn stop(&mut self) -> anyhow::Result<()>;
     fn is_available(&self) -> bool;
-    
+    /// Device node that was opened, so two gamepads don't drive the same wheel
+    fn device_path(&self) -> Option<&Path> {
+        None
+    }
+    /// Names of the effect types the device says it can play, empty if it doesn't say
+    fn supported_effects(&self) -> Vec<String> {
+        Vec::new()
+    }
+
 }
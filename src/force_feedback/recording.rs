@@ -0,0 +1,218 @@
+use super::RumbleTranslation;
+use crate::virtual_controller::RumbleState;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const RECORDING_VERSION: u32 = 1;
+/// Changes smaller than this aren't worth a sample
+const CHANGE_THRESHOLD: f32 = 0.001;
+pub const MIN_REPLAY_SPEED: f32 = 0.1;
+pub const MAX_REPLAY_SPEED: f32 = 4.0;
+
+/// The rumble the game sent at one moment
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RumbleSample {
+    /// Seconds since the recording started
+    pub time: f64,
+    pub large_motor: f32,
+    pub small_motor: f32,
+    pub force: f32,
+    /// Whether the game sent anything since the last sample, so the watchdog sees
+    /// the same silences on replay
+    pub updated: bool,
+    /// Whether an effect was still playing, see `RumbleState::active`
+    #[serde(default)]
+    pub active: bool,
+}
+
+/// The rumble a game sent during a session, for tuning the translation offline
+/// or attaching to a bug report. Passed through effects aren't included.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RumbleRecording {
+    pub version: u32,
+    /// Unix time the recording was made
+    pub created: u64,
+    /// Translation settings in use while recording
+    pub rumble: RumbleTranslation,
+    pub samples: Vec<RumbleSample>,
+}
+
+impl RumbleRecording {
+    pub fn duration(&self) -> Duration {
+        self.samples.last().map(|s| Duration::from_secs_f64(s.time)).unwrap_or_default()
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let recording: Self = serde_json::from_str(&contents)?;
+        if recording.version > RECORDING_VERSION {
+            anyhow::bail!("Recording is from a newer version of RoWheel");
+        }
+        Ok(recording)
+    }
+
+    /// Save into `dir` under a name from the creation time, returns the file's path
+    pub fn save(&self, dir: &Path) -> anyhow::Result<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!("rumble-{}.json", self.created));
+        // Not pretty printed, there can be a lot of samples
+        std::fs::write(&path, serde_json::to_string(self)?)?;
+        log::info!("Saved rumble recording to {:?}", path);
+        Ok(path)
+    }
+}
+
+/// Recordings in `dir`, newest first
+pub fn list_recordings(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    paths.reverse();
+    paths
+}
+
+/// Builds a recording from the rumble read each tick
+#[derive(Debug)]
+pub struct RumbleRecorder {
+    started: Instant,
+    recording: RumbleRecording,
+    last_update: Option<Instant>,
+}
+
+impl RumbleRecorder {
+    pub fn new(rumble: &RumbleTranslation, now: Instant) -> Self {
+        let created = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self {
+            started: now,
+            recording: RumbleRecording {
+                version: RECORDING_VERSION,
+                created,
+                rumble: rumble.clone(),
+                samples: Vec::new(),
+            },
+            last_update: None,
+        }
+    }
+
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.started)
+    }
+
+    /// Only keeps a sample when the game sent something or the mix changed
+    pub fn push(&mut self, rumble: &RumbleState, now: Instant) {
+        let updated = rumble.updated.is_some() && rumble.updated != self.last_update;
+        let changed = self.recording.samples.last().is_none_or(|last| {
+            last.active != rumble.active
+                || (last.large_motor - rumble.large_motor).abs() > CHANGE_THRESHOLD
+                || (last.small_motor - rumble.small_motor).abs() > CHANGE_THRESHOLD
+                || (last.force - rumble.force).abs() > CHANGE_THRESHOLD
+        });
+        if !updated && !changed {
+            return;
+        }
+        self.last_update = rumble.updated;
+        self.recording.samples.push(RumbleSample {
+            time: self.elapsed(now).as_secs_f64(),
+            large_motor: rumble.large_motor,
+            small_motor: rumble.small_motor,
+            force: rumble.force,
+            updated,
+            active: rumble.active,
+        });
+    }
+
+    /// The finished recording, with a closing sample so it lasts as long as it ran
+    pub fn finish(mut self, now: Instant) -> RumbleRecording {
+        if let Some(&last) = self.recording.samples.last() {
+            self.recording.samples.push(RumbleSample {
+                time: self.elapsed(now).as_secs_f64(),
+                updated: false,
+                ..last
+            });
+        }
+        self.recording
+    }
+}
+
+/// Plays a recording back in place of the game's rumble
+#[derive(Debug)]
+pub struct RumbleReplay {
+    recording: RumbleRecording,
+    speed: f32,
+    gain: f32,
+    /// Seconds into the recording
+    position: f64,
+    index: usize,
+    last_tick: Option<Instant>,
+    /// When the current sample's update would have arrived, for the watchdog
+    updated: Option<Instant>,
+}
+
+impl RumbleReplay {
+    pub fn new(recording: RumbleRecording, speed: f32, gain: f32) -> Self {
+        let mut replay = Self {
+            recording,
+            speed: 1.0,
+            gain: 1.0,
+            position: 0.0,
+            index: 0,
+            last_tick: None,
+            updated: None,
+        };
+        replay.adjust(speed, gain);
+        replay
+    }
+
+    /// Change the speed (1 is as recorded) and gain while playing
+    pub fn adjust(&mut self, speed: f32, gain: f32) {
+        self.speed = speed.clamp(MIN_REPLAY_SPEED, MAX_REPLAY_SPEED);
+        self.gain = gain.max(0.0);
+    }
+
+    /// How far through it is, 0 to 1
+    pub fn progress(&self) -> f32 {
+        let duration = self.recording.duration().as_secs_f64();
+        if duration <= 0.0 {
+            return 1.0;
+        }
+        (self.position / duration).min(1.0) as f32
+    }
+
+    /// The recorded rumble at `now`, None once it's over
+    pub fn state(&mut self, now: Instant) -> Option<RumbleState> {
+        let elapsed = self.last_tick.map(|last| now.saturating_duration_since(last)).unwrap_or_default();
+        self.last_tick = Some(now);
+        self.position += elapsed.as_secs_f64() * self.speed as f64;
+
+        let samples = &self.recording.samples;
+        if self.position > self.recording.duration().as_secs_f64() {
+            return None;
+        }
+        let mut reached = false;
+        while self.index + 1 < samples.len() && samples[self.index + 1].time <= self.position {
+            self.index += 1;
+            reached |= samples[self.index].updated;
+        }
+        let sample = samples.get(self.index)?;
+        if reached || (self.index == 0 && self.updated.is_none() && sample.updated) {
+            self.updated = Some(now);
+        }
+
+        Some(RumbleState {
+            large_motor: (sample.large_motor * self.gain).clamp(0.0, 1.0),
+            small_motor: (sample.small_motor * self.gain).clamp(0.0, 1.0),
+            force: (sample.force * self.gain).clamp(-1.0, 1.0),
+            updated: self.updated,
+            active: sample.active,
+        })
+    }
+}
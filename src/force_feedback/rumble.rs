@@ -0,0 +1,178 @@
+use crate::virtual_controller::RumbleState;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+use std::time::{Duration, Instant};
+
+/// Steering speed in position units per second where velocity mode picks a full direction
+const DIRECTION_VELOCITY: f32 = 0.1;
+/// Longest step the vibration phase advances, so a stall doesn't jump it around
+const MAX_STEP: Duration = Duration::from_millis(100);
+
+/// Shape of the wheel's own vibration effect
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum VibrationWaveform {
+    #[default]
+    Sine,
+    Square,
+}
+
+impl VibrationWaveform {
+    pub const ALL: [VibrationWaveform; 2] = [VibrationWaveform::Sine, VibrationWaveform::Square];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            VibrationWaveform::Sine => "Sine",
+            VibrationWaveform::Square => "Square",
+        }
+    }
+}
+
+/// How the two rumble motors become a force on the wheel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RumbleMode {
+    /// Large motor pulls right, small motor pulls left
+    #[default]
+    Difference,
+    /// Large motor is the force, small motor shakes the wheel on top
+    LargeTorque,
+    /// Both motors together shake the wheel back and forth
+    RoadTexture,
+    /// Strongest motor resists whichever way the wheel is turning
+    VelocityDirection,
+}
+
+impl RumbleMode {
+    pub const ALL: [RumbleMode; 4] = [
+        RumbleMode::Difference,
+        RumbleMode::LargeTorque,
+        RumbleMode::RoadTexture,
+        RumbleMode::VelocityDirection,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RumbleMode::Difference => "Large minus small",
+            RumbleMode::LargeTorque => "Large as force, small as vibration",
+            RumbleMode::RoadTexture => "Road texture",
+            RumbleMode::VelocityDirection => "Resist steering",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            RumbleMode::Difference => "The large motor pulls right and the small motor pulls left.",
+            RumbleMode::LargeTorque => "The large motor pulls one way, the small motor vibrates the wheel.",
+            RumbleMode::RoadTexture => "Both motors added together vibrate the wheel, with no pull either way.",
+            RumbleMode::VelocityDirection => "The stronger motor pushes against whichever way you're turning.",
+        }
+    }
+
+    /// Whether the mode shakes the wheel, so the vibration frequency matters
+    pub fn vibrates(&self) -> bool {
+        matches!(self, RumbleMode::LargeTorque | RumbleMode::RoadTexture)
+    }
+}
+
+/// Turning the game's rumble into wheel torque, tuned per wheel
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RumbleTranslation {
+    pub mode: RumbleMode,
+    pub gain: f32,
+    /// Added after the gain, to recenter games that only ever push one way
+    pub offset: f32,
+    pub inverted: bool,
+    /// Strongest torque the game's rumble can produce, 0 to 1
+    pub max_torque: f32,
+    /// Shake speed for the vibrating modes, and for native vibration at the lightest rumble
+    pub vibration_hz: f32,
+    /// Play the small motor as the wheel's own vibration effect instead of mixing it into the torque
+    pub native_vibration: bool,
+    pub vibration_gain: f32,
+    pub waveform: VibrationWaveform,
+}
+
+impl Default for RumbleTranslation {
+    fn default() -> Self {
+        Self {
+            mode: RumbleMode::Difference,
+            gain: 1.0,
+            offset: 0.0,
+            inverted: false,
+            max_torque: 1.0,
+            vibration_hz: 25.0,
+            native_vibration: true,
+            vibration_gain: 1.0,
+            waveform: VibrationWaveform::Sine,
+        }
+    }
+}
+
+/// Applies a `RumbleTranslation`, keeping the vibration phase between ticks
+#[derive(Debug, Default)]
+pub struct RumbleTranslator {
+    phase: f32,
+    last: Option<Instant>,
+    torque: f32,
+    vibration: f32,
+    vibration_period: Duration,
+}
+
+impl RumbleTranslator {
+    /// Torque from the last update, for display
+    pub fn torque(&self) -> f32 {
+        self.torque
+    }
+
+    /// Strength (0 to 1) and period of the native vibration from the last update
+    pub fn vibration(&self) -> (f32, Duration) {
+        (self.vibration, self.vibration_period)
+    }
+
+    /// Torque for the game's rumble right now. `steering_velocity` is in position units per second.
+    /// With `native_vibration` the small motor goes to `vibration()` and is left out of the torque.
+    pub fn update(
+        &mut self,
+        settings: &RumbleTranslation,
+        rumble: &RumbleState,
+        steering_velocity: f32,
+        now: Instant,
+        native_vibration: bool,
+    ) -> f32 {
+        let elapsed = self.last.map(|last| now.saturating_duration_since(last).min(MAX_STEP)).unwrap_or_default();
+        self.last = Some(now);
+        self.phase = (self.phase + TAU * settings.vibration_hz * elapsed.as_secs_f32()) % TAU;
+        let wave = self.phase.sin();
+
+        let large = rumble.large_motor.clamp(0.0, 1.0);
+        let mut small = rumble.small_motor.clamp(0.0, 1.0);
+        if native_vibration {
+            // Harder rumble buzzes faster, up to twice the base frequency
+            let hz = settings.vibration_hz.max(1.0) * (1.0 + small);
+            self.vibration = (small * settings.vibration_gain).clamp(0.0, 1.0);
+            self.vibration_period = Duration::from_secs_f32(1.0 / hz);
+            small = 0.0;
+        } else {
+            self.vibration = 0.0;
+        }
+
+        let raw = match settings.mode {
+            RumbleMode::Difference => large - small,
+            RumbleMode::LargeTorque => large + small * wave,
+            RumbleMode::RoadTexture => (large + small).min(1.0) * wave,
+            RumbleMode::VelocityDirection => {
+                -large.max(small) * (steering_velocity / DIRECTION_VELOCITY).tanh()
+            }
+        };
+
+        // Effects that already have a direction skip the motor translation
+        let raw = raw + rumble.force;
+        let torque = raw * settings.gain + settings.offset;
+        let torque = if settings.inverted { -torque } else { torque };
+        let max = settings.max_torque.clamp(0.0, 1.0);
+        self.torque = torque.clamp(-max, max);
+        self.torque
+    }
+}
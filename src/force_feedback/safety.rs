@@ -0,0 +1,207 @@
+use crate::input::{DeviceIdentity, InputState};
+use crate::mapping::InputSource;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Limits on what reaches the wheel, so a stuck game or a bad setting can't hurt anyone
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SafetySettings {
+    /// Fade the game's force out after this long without a rumble update and with nothing
+    /// playing on its own, 0 turns the watchdog off
+    pub watchdog_ms: u64,
+    /// How long the fade takes once the watchdog trips
+    pub fade_ms: u64,
+    /// Fastest the torque can change, in full scale per second
+    pub slew_rate: f32,
+    /// Wheel button that cuts all force feedback until it's pressed again
+    pub emergency_stop: Option<InputSource>,
+}
+
+impl Default for SafetySettings {
+    fn default() -> Self {
+        Self {
+            watchdog_ms: 3000,
+            fade_ms: 500,
+            slew_rate: 10.0,
+            emergency_stop: None,
+        }
+    }
+}
+
+impl SafetySettings {
+    pub fn devices_mut(&mut self) -> impl Iterator<Item = (&mut String, &str, &mut Option<DeviceIdentity>)> {
+        self.emergency_stop.iter_mut().map(|source| source.device_mut())
+    }
+
+    /// How much of the game's force to let through, 1 while it's updating and fading to 0
+    /// once it's been quiet for longer than the watchdog timeout
+    pub fn watchdog_scale(&self, last_update: Option<Instant>, now: Instant) -> f32 {
+        let Some(last_update) = last_update else {
+            return 1.0;
+        };
+        if self.watchdog_ms == 0 {
+            return 1.0;
+        }
+
+        let silence = now.saturating_duration_since(last_update);
+        let Some(fading) = silence.checked_sub(Duration::from_millis(self.watchdog_ms)) else {
+            return 1.0;
+        };
+        if self.fade_ms == 0 {
+            return 0.0;
+        }
+        1.0 - (fading.as_secs_f32() / Duration::from_millis(self.fade_ms).as_secs_f32()).min(1.0)
+    }
+}
+
+/// Last stage before the wheel: rate limits the torque and holds the emergency stop
+#[derive(Debug, Default)]
+pub struct TorqueGuard {
+    torque: f32,
+    last: Option<Instant>,
+    stopped: bool,
+    stop_held: bool,
+}
+
+impl TorqueGuard {
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    pub fn set_stopped(&mut self, stopped: bool) {
+        if stopped != self.stopped {
+            log::warn!("Force feedback {}", if stopped { "emergency stopped" } else { "resumed" });
+        }
+        self.stopped = stopped;
+    }
+
+    /// Torque to actually send for the wanted `torque`. Without `slew_limit` only the
+    /// emergency stop applies, for test effects that are there to show the wheel's own response.
+    pub fn update(&mut self, settings: &SafetySettings, input: &InputState, torque: f32, slew_limit: bool, now: Instant) -> f32 {
+        let stop_pressed = settings.emergency_stop
+            .as_ref()
+            .and_then(|s| s.read_pressed(input))
+            .unwrap_or(false);
+        if stop_pressed && !self.stop_held {
+            self.set_stopped(!self.stopped);
+        }
+        self.stop_held = stop_pressed;
+
+        let elapsed = self.last.map(|last| now.saturating_duration_since(last)).unwrap_or_default();
+        self.last = Some(now);
+
+        // Stopping is instant, it's the one change that should never be slowed down
+        if self.stopped {
+            self.torque = 0.0;
+            return 0.0;
+        }
+
+        if !slew_limit {
+            self.torque = torque;
+            return torque;
+        }
+        let max_step = settings.slew_rate.max(0.0) * elapsed.as_secs_f32();
+        self.torque += (torque - self.torque).clamp(-max_step, max_step);
+        self.torque
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ButtonBinding;
+    use std::collections::HashMap;
+
+    const STOP_BUTTON: u32 = 3;
+
+    fn settings() -> SafetySettings {
+        SafetySettings {
+            emergency_stop: Some(InputSource::Button(ButtonBinding {
+                device_id: "0".to_string(),
+                device_name: "Wheel".to_string(),
+                identity: None,
+                button_code: STOP_BUTTON,
+            })),
+            ..Default::default()
+        }
+    }
+
+    fn stop_button(pressed: bool) -> InputState {
+        let mut input = InputState::default();
+        input.buttons.insert("0".to_string(), HashMap::from([(STOP_BUTTON, pressed)]));
+        input
+    }
+
+    fn ms(start: Instant, ms: u64) -> Instant {
+        start + Duration::from_millis(ms)
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 0.001, "expected {expected}, got {actual}");
+    }
+
+    #[test]
+    fn watchdog_fades_after_timeout() {
+        let settings = SafetySettings::default();
+        let start = Instant::now();
+
+        assert_near(settings.watchdog_scale(None, ms(start, 60_000)), 1.0);
+        assert_near(settings.watchdog_scale(Some(start), ms(start, 2999)), 1.0);
+        assert_near(settings.watchdog_scale(Some(start), ms(start, 3000)), 1.0);
+        assert_near(settings.watchdog_scale(Some(start), ms(start, 3250)), 0.5);
+        assert_near(settings.watchdog_scale(Some(start), ms(start, 3500)), 0.0);
+        assert_near(settings.watchdog_scale(Some(start), ms(start, 60_000)), 0.0);
+    }
+
+    #[test]
+    fn watchdog_off_and_instant_fade() {
+        let start = Instant::now();
+        let off = SafetySettings { watchdog_ms: 0, ..Default::default() };
+        assert_near(off.watchdog_scale(Some(start), ms(start, 60_000)), 1.0);
+
+        let instant = SafetySettings { fade_ms: 0, ..Default::default() };
+        assert_near(instant.watchdog_scale(Some(start), ms(start, 2999)), 1.0);
+        assert_near(instant.watchdog_scale(Some(start), ms(start, 3001)), 0.0);
+    }
+
+    #[test]
+    fn slew_limits_each_tick() {
+        // 10 full scale per second is 0.1 per 10ms tick
+        let settings = SafetySettings::default();
+        let input = InputState::default();
+        let mut guard = TorqueGuard::default();
+        let start = Instant::now();
+
+        assert_near(guard.update(&settings, &input, 1.0, true, start), 0.0);
+        assert_near(guard.update(&settings, &input, 1.0, true, ms(start, 10)), 0.1);
+        assert_near(guard.update(&settings, &input, 1.0, true, ms(start, 20)), 0.2);
+        assert_near(guard.update(&settings, &input, -1.0, true, ms(start, 30)), 0.1);
+        // A small change inside the limit goes straight through
+        assert_near(guard.update(&settings, &input, 0.15, true, ms(start, 40)), 0.15);
+
+        assert_near(guard.update(&settings, &input, -1.0, false, ms(start, 50)), -1.0);
+    }
+
+    #[test]
+    fn emergency_stop_toggles_on_press_only() {
+        let settings = settings();
+        let mut guard = TorqueGuard::default();
+        let start = Instant::now();
+        guard.update(&settings, &stop_button(false), 0.0, false, start);
+
+        // Holding the button stops once, it doesn't flicker
+        for tick in 1..=5 {
+            assert_near(guard.update(&settings, &stop_button(true), 1.0, false, ms(start, tick * 10)), 0.0);
+            assert!(guard.is_stopped());
+        }
+        assert_near(guard.update(&settings, &stop_button(false), 1.0, false, ms(start, 60)), 0.0);
+        assert!(guard.is_stopped());
+
+        // The next press resumes, and the torque ramps back up from nothing
+        assert_near(guard.update(&settings, &stop_button(true), 1.0, true, ms(start, 70)), 0.1);
+        assert!(!guard.is_stopped());
+        assert_near(guard.update(&settings, &stop_button(true), 1.0, true, ms(start, 80)), 0.2);
+        assert!(!guard.is_stopped());
+    }
+}
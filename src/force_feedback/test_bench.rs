@@ -0,0 +1,90 @@
+use std::f32::consts::TAU;
+use std::time::{Duration, Instant};
+
+/// Steering position (-1..1 over the whole wheel rotation) where the test spring hits full force
+const SPRING_FULL_AT: f32 = 0.25;
+const BUZZ_HZ: f32 = 20.0;
+/// Quiet time before and after the step, so the wheel's response can be seen on both edges
+const STEP_LEAD: Duration = Duration::from_millis(500);
+const STEP_HOLD: Duration = Duration::from_millis(1500);
+
+/// Canned effects for checking the wheel's force feedback without a game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestEffect {
+    Sweep,
+    Buzz,
+    Spring,
+    Step,
+}
+
+impl TestEffect {
+    pub const ALL: [TestEffect; 4] = [TestEffect::Sweep, TestEffect::Buzz, TestEffect::Spring, TestEffect::Step];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TestEffect::Sweep => "Sweep",
+            TestEffect::Buzz => "Buzz",
+            TestEffect::Spring => "Spring",
+            TestEffect::Step => "Step",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            TestEffect::Sweep => "Constant force swinging smoothly right, then left",
+            TestEffect::Buzz => "20 Hz sine vibration",
+            TestEffect::Spring => "Pulls the wheel to center, turn it to feel it",
+            TestEffect::Step => "Full force on and off at once, to see how the wheel responds",
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        match self {
+            TestEffect::Sweep => Duration::from_secs(6),
+            TestEffect::Buzz => Duration::from_secs(2),
+            TestEffect::Spring => Duration::from_secs(10),
+            TestEffect::Step => STEP_LEAD * 2 + STEP_HOLD,
+        }
+    }
+}
+
+/// A test effect that's playing
+#[derive(Debug, Clone, Copy)]
+pub struct TestRun {
+    effect: TestEffect,
+    strength: f32,
+    started: Instant,
+}
+
+impl TestRun {
+    pub fn new(effect: TestEffect, strength: f32, now: Instant) -> Self {
+        Self {
+            effect,
+            strength: strength.clamp(0.0, 1.0),
+            started: now,
+        }
+    }
+
+    pub fn effect(&self) -> TestEffect {
+        self.effect
+    }
+
+    /// Torque to play at `now`, None once the test is over
+    pub fn torque(&self, position: Option<f32>, now: Instant) -> Option<f32> {
+        let elapsed = now.saturating_duration_since(self.started);
+        if elapsed >= self.effect.duration() {
+            return None;
+        }
+        let t = elapsed.as_secs_f32();
+
+        let torque = match self.effect {
+            TestEffect::Sweep => (t / self.effect.duration().as_secs_f32() * TAU).sin(),
+            TestEffect::Buzz => (t * BUZZ_HZ * TAU).sin(),
+            TestEffect::Spring => -(position.unwrap_or(0.0) / SPRING_FULL_AT).clamp(-1.0, 1.0),
+            TestEffect::Step => {
+                if elapsed >= STEP_LEAD && elapsed < STEP_LEAD + STEP_HOLD { 1.0 } else { 0.0 }
+            }
+        };
+        Some(torque * self.strength)
+    }
+}
@@ -1,6 +1,8 @@
 use super::directinput_ffi::*;
-use super::{ForceFeedback, RumbleState};
+use super::{DeviceSettings, ForceFeedback};
+use crate::input::DeviceIdentity;
 use std::ffi::c_void;
+use std::path::PathBuf;
 use std::ptr;
 use windows::core::{GUID, PCWSTR};
 use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
@@ -182,7 +184,9 @@ fn create_message_window() -> anyhow::Result<HWND> {
 unsafe impl Send for ForceFeedbackDevice {}
 
 impl ForceFeedbackDevice {
-    pub fn new(_device_path: Option<&str>) -> anyhow::Result<Self> {
+    /// Open the FF device matching `target`, or the first FF capable device if there's no target.
+    /// `taken` is unused, DirectInput devices have no path for other pads to claim.
+    pub fn new(target: Option<&DeviceIdentity>, _taken: &[PathBuf]) -> anyhow::Result<Self> {
         let mut ff = Self {
             dinput: ptr::null_mut(),
             device: ptr::null_mut(),
@@ -209,14 +213,14 @@ impl ForceFeedbackDevice {
             Err(e) => log::warn!("Failed to create message window: {}", e),
         }
 
-        if let Err(e) = ff.initialize() {
+        if let Err(e) = ff.initialize(target) {
             log::warn!("Failed to initialize DirectInput force feedback: {}", e);
         }
 
         Ok(ff)
     }
 
-    fn initialize(&mut self) -> anyhow::Result<()> {
+    fn initialize(&mut self, target: Option<&DeviceIdentity>) -> anyhow::Result<()> {
         unsafe {
             let dinput_name: Vec<u16> = "dinput8.dll\0".encode_utf16().collect();
             let hinst = LoadLibraryW(PCWSTR(dinput_name.as_ptr()))?;
@@ -236,7 +240,7 @@ impl ForceFeedbackDevice {
 
             log::info!("DirectInput8 initialized");
 
-            self.find_ff_device()?;
+            self.find_ff_device(target)?;
 
             let dev_vtbl = &*(*self.device).lpvtbl;
 
@@ -283,11 +287,12 @@ impl ForceFeedbackDevice {
         }
     }
 
-    unsafe fn find_ff_device(&mut self) -> anyhow::Result<()> {
-        struct EnumContext {
+    unsafe fn find_ff_device(&mut self, target: Option<&DeviceIdentity>) -> anyhow::Result<()> {
+        struct EnumContext<'a> {
             dinput: *mut IDirectInput8W,
             device: *mut IDirectInputDevice8W,
             found: bool,
+            target: Option<&'a DeviceIdentity>,
         }
 
         unsafe extern "system" fn enum_callback(
@@ -301,6 +306,20 @@ impl ForceFeedbackDevice {
             let name = String::from_utf16_lossy(&inst.tsz_product_name[..name_len]);
             log::info!("Found FF device: {}", name);
 
+            if let Some(target) = ctx.target {
+                // guidProduct packs the USB ids as MAKELONG(vid, pid)
+                let identity = DeviceIdentity {
+                    name: name.clone(),
+                    vendor_id: Some((inst.guid_product.data1 & 0xffff) as u16),
+                    product_id: Some((inst.guid_product.data1 >> 16) as u16),
+                    ..Default::default()
+                };
+                if target.match_score(&identity).is_none() {
+                    log::info!("Skipping {}, doesn't match {}", name, target.name);
+                    return DIENUM_CONTINUE;
+                }
+            }
+
             let vtbl = &*(*ctx.dinput).lpvtbl;
             let hr = (vtbl.create_device)(ctx.dinput, &inst.guid_instance, &mut ctx.device, ptr::null_mut());
 
@@ -317,6 +336,7 @@ impl ForceFeedbackDevice {
             dinput: self.dinput,
             device: ptr::null_mut(),
             found: false,
+            target,
         };
 
         let vtbl = &*(*self.dinput).lpvtbl;
@@ -454,16 +474,46 @@ impl ForceFeedbackDevice {
 }
 
 impl ForceFeedback for ForceFeedbackDevice {
-    fn apply_rumble(&mut self, rumble: &RumbleState) -> anyhow::Result<()> {
+    /// DirectInput only has autocenter on/off and some drivers need it set before acquiring,
+    /// so it stays off and only the gain is applied here
+    fn configure(&mut self, settings: &DeviceSettings) -> anyhow::Result<()> {
+        if self.device.is_null() {
+            return Ok(());
+        }
+
+        unsafe {
+            // DIPROP_FFGAIN = MAKEDIPROP(7)
+            let diprop_ffgain: *const GUID = 7 as *const GUID;
+
+            let prop = DIPROPDWORD {
+                diph: DIPROPHEADER {
+                    dw_size: std::mem::size_of::<DIPROPDWORD>() as u32,
+                    dw_header_size: std::mem::size_of::<DIPROPHEADER>() as u32,
+                    dw_obj: 0,
+                    dw_how: DIPH_DEVICE,
+                },
+                dw_data: (settings.gain.clamp(0.0, 1.0) * 10000.0) as u32,
+            };
+
+            let dev_vtbl = &*(*self.device).lpvtbl;
+            let hr = (dev_vtbl.set_property)(self.device, diprop_ffgain, &prop.diph);
+            if hr.is_err() {
+                return Err(anyhow::anyhow!("Failed to set force feedback gain: {:?}", hr));
+            }
+        }
+
+        log::info!("Force feedback gain {:.0}%", settings.gain * 100.0);
+        Ok(())
+    }
+
+    fn apply_torque(&mut self, torque: f32) -> anyhow::Result<()> {
         if !self.available {
             return Ok(());
         }
 
-        let large = rumble.large_motor.clamp(0.0, 1.0);
-        let small = rumble.small_motor.clamp(0.0, 1.0);
-        let magnitude = ((large - small) * 10000.0) as i32;
+        let magnitude = (torque.clamp(-1.0, 1.0) * 10000.0) as i32;
 
-        log::info!("Force feedback: large={:.3}, small={:.3} → magnitude={}", large, small, magnitude);
+        log::debug!("Force feedback: torque={:.3} → magnitude={}", torque, magnitude);
 
         self.update_force(magnitude)
     }
@@ -4,18 +4,95 @@ pub mod evdev_reader;
 
 pub use reader::*;
 
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct InputDevice {
+    /// Session-only id from gilrs, changes with plug order
     pub id: String,
     pub name: String,
+    pub identity: DeviceIdentity,
     pub axes: Vec<AxisInfo>,
     pub buttons: Vec<ButtonInfo>,
     pub has_force_feedback: bool,
 }
 
+/// Hardware identity of a device that survives replugging and reboots
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeviceIdentity {
+    pub name: String,
+    pub vendor_id: Option<u16>,
+    pub product_id: Option<u16>,
+    /// USB serial number (udev ID_SERIAL_SHORT), only on linux
+    pub serial: Option<String>,
+    /// evdev uniq string, usually the bluetooth MAC or serial
+    pub uniq: Option<String>,
+    /// evdev physical path, tied to the USB port the device is plugged into
+    pub phys: Option<String>,
+}
+
+impl DeviceIdentity {
+    /// How well a connected device matches this saved identity.
+    /// None means it can't be the same device, higher is a better match.
+    pub fn match_score(&self, other: &DeviceIdentity) -> Option<u32> {
+        let mut score = 0;
+
+        let ids_known = self.vendor_id.is_some() && self.product_id.is_some()
+            && other.vendor_id.is_some() && other.product_id.is_some();
+        if ids_known {
+            if self.vendor_id != other.vendor_id || self.product_id != other.product_id {
+                return None;
+            }
+            score += 1;
+        } else if self.name != other.name {
+            // Without vendor/product ids the name is all we have
+            return None;
+        }
+
+        // Serial and uniq identify the exact unit so a mismatch rules it out
+        for (a, b) in [(&self.serial, &other.serial), (&self.uniq, &other.uniq)] {
+            if let (Some(a), Some(b)) = (non_empty(a), non_empty(b)) {
+                if a != b {
+                    return None;
+                }
+                score += 8;
+            }
+        }
+
+        // Phys only changes when the device moves to another port so it's just a tiebreaker
+        if let (Some(a), Some(b)) = (non_empty(&self.phys), non_empty(&other.phys)) {
+            if a == b {
+                score += 4;
+            }
+        }
+
+        if self.name == other.name {
+            score += 2;
+        }
+
+        Some(score)
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().filter(|s| !s.is_empty())
+}
+
+/// Find the connected device that best matches a saved identity
+pub fn resolve_device<'a>(
+    devices: &'a HashMap<String, InputDevice>,
+    identity: &DeviceIdentity,
+) -> Option<&'a InputDevice> {
+    devices
+        .values()
+        .filter_map(|device| identity.match_score(&device.identity).map(|score| (score, device)))
+        // Ties go to the lowest id so identical devices resolve the same way every frame
+        .max_by(|(a_score, a), (b_score, b)| a_score.cmp(b_score).then_with(|| b.id.cmp(&a.id)))
+        .map(|(_, device)| device)
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct AxisInfo {
@@ -1,4 +1,4 @@
-use super::{AxisInfo, ButtonInfo, InputDevice, InputEvent, InputState};
+use super::{AxisInfo, ButtonInfo, DeviceIdentity, InputDevice, InputEvent, InputState};
 use gilrs::{Axis, Button, EventType, Gilrs, GilrsBuilder};
 #[cfg(target_os = "linux")]
 use gilrs::GamepadId;
@@ -52,20 +52,18 @@ impl InputReader {
             
             let has_ff = gamepad.is_ff_supported();
 
-            let device = InputDevice {
-                id: device_id.clone(),
+            #[allow(unused_mut)]
+            let mut identity = DeviceIdentity {
                 name: gamepad.name().to_string(),
-                axes: self.get_axes_info(&gamepad),
-                buttons: Self::get_buttons_info(&gamepad),
-                has_force_feedback: has_ff,
+                vendor_id: gamepad.vendor_id(),
+                product_id: gamepad.product_id(),
+                ..Default::default()
             };
 
-            log::info!("Found device: {} ({}) - FF: {}",
-                device.name, device_id, device.has_force_feedback);
-            
             #[cfg(target_os = "linux")]
             if let Some(path) = self.find_device_path(&gamepad) {
                 log::info!("Found evdev path for {}: {}", gamepad.name(), path.display());
+                self.fill_evdev_identity(&path, &mut identity);
                 if let Ok(reader) = EvdevReader::new(&path) {
                     self.evdev_readers.insert(id, reader.receiver);
                 } else {
@@ -75,6 +73,18 @@ impl InputReader {
                 log::warn!("No evdev path found for {}", gamepad.name());
             }
 
+            let device = InputDevice {
+                id: device_id.clone(),
+                name: gamepad.name().to_string(),
+                identity,
+                axes: self.get_axes_info(&gamepad),
+                buttons: Self::get_buttons_info(&gamepad),
+                has_force_feedback: has_ff,
+            };
+
+            log::info!("Found device: {} ({}) - FF: {} - {:?}",
+                device.name, device_id, device.has_force_feedback, device.identity);
+
             self.devices.insert(device_id, device);
         }
     }
@@ -165,6 +175,37 @@ impl InputReader {
         None
     }
 
+    /// Fill in the parts of the identity gilrs doesn't know about (phys, uniq and the USB serial)
+    #[cfg(target_os = "linux")]
+    fn fill_evdev_identity(&self, path: &std::path::Path, identity: &mut DeviceIdentity) {
+        match evdev::Device::open(path) {
+            Ok(device) => {
+                identity.phys = device.physical_path().map(|s| s.to_string());
+                identity.uniq = device.unique_name().map(|s| s.to_string());
+            }
+            Err(e) => log::warn!("Failed to open {} for identity: {}", path.display(), e),
+        }
+
+        let Ok(mut enumerator) = libudev::Enumerator::new(&self.udev) else {
+            return;
+        };
+        if enumerator.match_subsystem("input").is_err() {
+            return;
+        }
+        let Ok(devices) = enumerator.scan_devices() else {
+            return;
+        };
+        for device in devices {
+            if device.devnode() == Some(path) {
+                identity.serial = device
+                    .property_value("ID_SERIAL_SHORT")
+                    .and_then(|s| s.to_str())
+                    .map(|s| s.to_string());
+                break;
+            }
+        }
+    }
+
     fn get_axes_info(&self, gamepad: &gilrs::Gamepad) -> Vec<AxisInfo> {
         let mut axes = Vec::new();
 
@@ -281,6 +322,7 @@ impl InputReader {
     pub fn poll(&mut self) -> Vec<InputEvent> {
         let mut events = Vec::new();
         let mut refresh_needed = false;
+        let mut connected_ids = Vec::new();
 
         while let Some(event) = self.gilrs.next_event() {
             log::trace!("Gilrs event: {:?}", event.event);
@@ -377,12 +419,9 @@ impl InputReader {
                     });
                 }
                 EventType::Connected => {
+                    // The device only shows up in the map after the refresh below
                     refresh_needed = true;
-                    if let Some(device) = self.devices.get(&format!("{:?}", event.id)) {
-                        events.push(InputEvent::DeviceConnected {
-                            device: device.clone(),
-                        });
-                    }
+                    connected_ids.push(format!("{:?}", event.id));
                 }
                 EventType::Disconnected => {
                     events.push(InputEvent::DeviceDisconnected { device_id: format!("{:?}", event.id) });
@@ -432,6 +471,14 @@ impl InputReader {
 
         if refresh_needed {
             self.refresh_devices();
+
+            for id in connected_ids {
+                if let Some(device) = self.devices.get(&id) {
+                    events.push(InputEvent::DeviceConnected {
+                        device: device.clone(),
+                    });
+                }
+            }
         }
 
         events
@@ -1,15 +1,39 @@
 mod app;
 mod calibration;
 mod config;
+mod curve;
+mod engine;
 mod force_feedback;
 mod input;
+mod mapping;
+mod profiles;
+mod shifter;
 mod virtual_controller;
 
 use app::RoWheelApp;
+use std::path::PathBuf;
+
+fn parse_args() {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            match args.next() {
+                Some(path) => config::set_config_override(PathBuf::from(path)),
+                None => log::warn!("--config needs a path"),
+            }
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            config::set_config_override(PathBuf::from(path));
+        } else {
+            log::warn!("Ignoring unknown argument: {}", arg);
+        }
+    }
+}
 
 fn main() -> eframe::Result<()> {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
+    parse_args();
+
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([600.0, 500.0])
@@ -21,6 +45,6 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "RoWheel",
         native_options,
-        Box::new(|cc| Ok(Box::new(RoWheelApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(RoWheelApp::new(cc)?))),
     )
 }
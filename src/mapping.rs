@@ -0,0 +1,654 @@
+use crate::config::{AxisBinding, ButtonBinding};
+use crate::curve::ResponseCurve;
+use crate::input::{DeviceIdentity, InputEvent, InputState};
+use crate::virtual_controller::{
+    self, ControllerKind, HatDirection, JoystickSettings, MouseButton, XboxButton, XboxControllerState,
+    JOYSTICK_AXIS_NAMES, KEYBOARD_KEYS, MAX_JOYSTICK_AXES,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// One direction of a hat switch that reports as an axis (evdev ABS_HAT*)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HatBinding {
+    pub device_id: String,
+    pub device_name: String,
+    #[serde(default)]
+    pub identity: Option<DeviceIdentity>,
+    pub axis_code: u32,
+    /// true for the positive direction (right/down), false for negative (left/up)
+    pub positive: bool,
+}
+
+impl HatBinding {
+    pub fn is_pressed(&self, value: f32) -> bool {
+        if self.positive {
+            value > 0.5
+        } else {
+            value < -0.5
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum InputSource {
+    Axis(AxisBinding),
+    Button(ButtonBinding),
+    Hat(HatBinding),
+}
+
+impl InputSource {
+    /// device_id, device_name and identity of the device this source reads from
+    pub fn device(&self) -> (&str, &str, &Option<DeviceIdentity>) {
+        match self {
+            Self::Axis(a) => (&a.device_id, &a.device_name, &a.identity),
+            Self::Button(b) => (&b.device_id, &b.device_name, &b.identity),
+            Self::Hat(h) => (&h.device_id, &h.device_name, &h.identity),
+        }
+    }
+
+    pub fn device_mut(&mut self) -> (&mut String, &str, &mut Option<DeviceIdentity>) {
+        match self {
+            Self::Axis(a) => (&mut a.device_id, &a.device_name, &mut a.identity),
+            Self::Button(b) => (&mut b.device_id, &b.device_name, &mut b.identity),
+            Self::Hat(h) => (&mut h.device_id, &h.device_name, &mut h.identity),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Axis(a) => match a.split {
+                Some(ref split) => format!("{} - Axis {} ({})", a.device_name, a.axis_code, split.half.name().to_lowercase()),
+                None => format!("{} - Axis {}", a.device_name, a.axis_code),
+            },
+            Self::Button(b) => format!("{} - Button {}", b.device_name, b.button_code),
+            Self::Hat(h) => format!(
+                "{} - Hat {} {}",
+                h.device_name,
+                h.axis_code,
+                if h.positive { "+" } else { "-" }
+            ),
+        }
+    }
+
+    /// Value as a stick deflection (-1..1)
+    fn read_stick(&self, input: &InputState) -> Option<f32> {
+        match self {
+            Self::Axis(a) => input.get_axis(&a.device_id, a.axis_code).map(|v| a.normalize(v)),
+            _ => self.read_pressed(input).map(|p| if p { 1.0 } else { 0.0 }),
+        }
+    }
+
+    /// Value as a trigger (0..1)
+    fn read_trigger(&self, input: &InputState) -> Option<f32> {
+        match self {
+            Self::Axis(a) => input.get_axis(&a.device_id, a.axis_code).map(|v| a.normalize_trigger(v)),
+            _ => self.read_pressed(input).map(|p| if p { 1.0 } else { 0.0 }),
+        }
+    }
+
+    pub fn read_pressed(&self, input: &InputState) -> Option<bool> {
+        match self {
+            // Axes act as a button once they're past halfway
+            Self::Axis(_) => self.read_trigger(input).map(|v| v > 0.5),
+            Self::Button(b) => input.get_button(&b.device_id, b.button_code),
+            Self::Hat(h) => input.get_axis(&h.device_id, h.axis_code).map(|v| h.is_pressed(v)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StickAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+}
+
+impl StickAxis {
+    pub const ALL: [StickAxis; 4] = [Self::LeftX, Self::LeftY, Self::RightX, Self::RightY];
+
+    fn value_mut<'a>(&self, state: &'a mut XboxControllerState) -> &'a mut f32 {
+        match self {
+            Self::LeftX => &mut state.left_stick_x,
+            Self::LeftY => &mut state.left_stick_y,
+            Self::RightX => &mut state.right_stick_x,
+            Self::RightY => &mut state.right_stick_y,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputTarget {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    /// One direction of a stick axis, read like a trigger. Two pedals on opposite
+    /// halves of the same axis combine into one signed axis (positive minus negative).
+    StickHalf { axis: StickAxis, positive: bool },
+    LeftTrigger,
+    RightTrigger,
+    Button(XboxButton),
+    /// Joystick axis by index, centered like a stick
+    JoystickAxis(usize),
+    /// Joystick axis by index, read like a trigger across the axis' whole range
+    JoystickPedal(usize),
+    JoystickButton(usize),
+    JoystickHat { hat: usize, direction: HatDirection },
+    /// Keyboard key by Linux input event code
+    Key(u16),
+    /// Mouse movement, the axis sets how fast it goes
+    MouseX,
+    MouseY,
+    MouseButton(MouseButton),
+}
+
+impl OutputTarget {
+    /// Every output the gamepad `controller` has
+    pub fn all(controller: ControllerKind, joystick: &JoystickSettings) -> Vec<OutputTarget> {
+        let mut targets = Self::gamepad(controller, joystick);
+        // The keyboard and mouse sit next to whichever gamepad it is
+        if cfg!(target_os = "linux") {
+            targets.extend(KEYBOARD_KEYS.iter().map(|(code, _)| Self::Key(*code)));
+            targets.extend([Self::MouseX, Self::MouseY]);
+            targets.extend(MouseButton::ALL.iter().map(|b| Self::MouseButton(*b)));
+        }
+        targets
+    }
+
+    fn gamepad(controller: ControllerKind, joystick: &JoystickSettings) -> Vec<OutputTarget> {
+        if controller == ControllerKind::Joystick {
+            let axes = joystick.axes.min(MAX_JOYSTICK_AXES);
+            let mut targets: Vec<OutputTarget> = (0..axes).map(Self::JoystickAxis).collect();
+            targets.extend((0..axes).map(Self::JoystickPedal));
+            targets.extend((0..joystick.buttons).map(Self::JoystickButton));
+            for hat in 0..joystick.hats {
+                targets.extend(HatDirection::ALL.iter().map(|&direction| Self::JoystickHat { hat, direction }));
+            }
+            return targets;
+        }
+
+        let mut targets = vec![
+            Self::LeftStickX,
+            Self::LeftStickY,
+            Self::RightStickX,
+            Self::RightStickY,
+        ];
+        for axis in StickAxis::ALL {
+            targets.push(Self::StickHalf { axis, positive: true });
+            targets.push(Self::StickHalf { axis, positive: false });
+        }
+        targets.extend([Self::LeftTrigger, Self::RightTrigger]);
+        targets.extend(XboxButton::ALL.iter().map(|b| Self::Button(*b)));
+        targets
+    }
+
+    pub fn is_stick(&self) -> bool {
+        matches!(self, Self::LeftStickX | Self::LeftStickY | Self::RightStickX | Self::RightStickY
+            | Self::JoystickAxis(_) | Self::MouseX | Self::MouseY)
+    }
+
+    pub fn is_keyboard_mouse(&self) -> bool {
+        matches!(self, Self::Key(_) | Self::MouseX | Self::MouseY | Self::MouseButton(_))
+    }
+
+    /// Whether the gamepad `controller` has this output
+    pub fn exists_on(&self, controller: ControllerKind, joystick: &JoystickSettings) -> bool {
+        let is_joystick = controller == ControllerKind::Joystick;
+        match *self {
+            Self::JoystickAxis(index) | Self::JoystickPedal(index) => is_joystick && index < joystick.axes.min(MAX_JOYSTICK_AXES),
+            Self::JoystickButton(index) => is_joystick && index < joystick.buttons,
+            Self::JoystickHat { hat, .. } => is_joystick && hat < joystick.hats,
+            Self::Key(_) | Self::MouseX | Self::MouseY | Self::MouseButton(_) => cfg!(target_os = "linux"),
+            _ => !is_joystick,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        let axis_name = |index: usize| JOYSTICK_AXIS_NAMES.get(index).copied().unwrap_or("?");
+        let name = match self {
+            Self::LeftStickX => "Left Stick X",
+            Self::LeftStickY => "Left Stick Y",
+            Self::RightStickX => "Right Stick X",
+            Self::RightStickY => "Right Stick Y",
+            Self::StickHalf { axis, positive } => match (axis, positive) {
+                (StickAxis::LeftX, true) => "Left Stick X +",
+                (StickAxis::LeftX, false) => "Left Stick X -",
+                (StickAxis::LeftY, true) => "Left Stick Y +",
+                (StickAxis::LeftY, false) => "Left Stick Y -",
+                (StickAxis::RightX, true) => "Right Stick X +",
+                (StickAxis::RightX, false) => "Right Stick X -",
+                (StickAxis::RightY, true) => "Right Stick Y +",
+                (StickAxis::RightY, false) => "Right Stick Y -",
+            },
+            Self::LeftTrigger => "Left Trigger",
+            Self::RightTrigger => "Right Trigger",
+            Self::Button(b) => b.name(),
+            Self::JoystickAxis(index) => return format!("Joystick {}", axis_name(*index)),
+            Self::JoystickPedal(index) => return format!("Joystick {} (pedal)", axis_name(*index)),
+            Self::JoystickButton(index) => return format!("Joystick Button {}", index + 1),
+            Self::JoystickHat { hat, direction } => return format!("Joystick Hat {} {}", hat + 1, direction.name()),
+            Self::Key(code) => return format!("Key {}", virtual_controller::key_name(*code)),
+            Self::MouseX => "Mouse X",
+            Self::MouseY => "Mouse Y",
+            Self::MouseButton(b) => b.name(),
+        };
+        name.to_string()
+    }
+}
+
+/// What a binding is for, so calibration and force feedback can find the wheel's main controls
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BindingRole {
+    Steering,
+    Throttle,
+    Brake,
+    Clutch,
+    ShiftUp,
+    ShiftDown,
+}
+
+impl BindingRole {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Steering => "Steering",
+            Self::Throttle => "Throttle",
+            Self::Brake => "Brake",
+            Self::Clutch => "Clutch",
+            Self::ShiftUp => "Shift Up",
+            Self::ShiftDown => "Shift Down",
+        }
+    }
+
+    pub fn default_target(&self, controller: ControllerKind) -> OutputTarget {
+        if controller == ControllerKind::Joystick {
+            // Laid out like a typical DirectInput wheel
+            return match self {
+                Self::Steering => OutputTarget::JoystickAxis(0),
+                Self::Throttle => OutputTarget::JoystickPedal(1),
+                Self::Brake => OutputTarget::JoystickPedal(2),
+                Self::Clutch => OutputTarget::JoystickPedal(5),
+                Self::ShiftUp => OutputTarget::JoystickButton(4),
+                Self::ShiftDown => OutputTarget::JoystickButton(5),
+            };
+        }
+        match self {
+            Self::Steering => OutputTarget::LeftStickX,
+            Self::Throttle => OutputTarget::RightTrigger,
+            Self::Brake => OutputTarget::LeftTrigger,
+            Self::Clutch => OutputTarget::LeftStickY,
+            Self::ShiftUp => OutputTarget::Button(XboxButton::Y),
+            Self::ShiftDown => OutputTarget::Button(XboxButton::X),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Binding {
+    pub label: String,
+    #[serde(default)]
+    pub role: Option<BindingRole>,
+    pub source: InputSource,
+    pub target: OutputTarget,
+}
+
+impl Binding {
+    /// Swap in a new source, carrying over the curve and rotation so rebinding doesn't lose tuning
+    pub fn set_source(&mut self, mut source: InputSource) {
+        if let (InputSource::Axis(old), InputSource::Axis(new)) = (&self.source, &mut source) {
+            new.curve = old.curve.clone();
+            if old.rotation.is_some() {
+                new.rotation = old.rotation.clone();
+            }
+        }
+        self.source = source;
+    }
+
+    /// The axis value before the response curve, in the range the curve works on (0..1),
+    /// plus the output after it. None for non-axis sources or disconnected devices.
+    pub fn curve_input(&self, input: &InputState) -> Option<(f32, f32)> {
+        let InputSource::Axis(ref axis) = self.source else {
+            return None;
+        };
+        let raw = input.get_axis(&axis.device_id, axis.axis_code)?;
+        let x = if self.target.is_stick() {
+            axis.normalize_linear(raw).abs()
+        } else {
+            axis.normalize_trigger_linear(raw)
+        };
+        Some((x, axis.curve.apply(x)))
+    }
+}
+
+/// Drive a target fully, as if its source was pressed all the way
+pub fn press_target(state: &mut XboxControllerState, target: OutputTarget) {
+    match target {
+        OutputTarget::LeftStickX => state.left_stick_x = 1.0,
+        OutputTarget::LeftStickY => state.left_stick_y = 1.0,
+        OutputTarget::RightStickX => state.right_stick_x = 1.0,
+        OutputTarget::RightStickY => state.right_stick_y = 1.0,
+        OutputTarget::StickHalf { axis, positive } => *axis.value_mut(state) = if positive { 1.0 } else { -1.0 },
+        OutputTarget::LeftTrigger => state.left_trigger = 1.0,
+        OutputTarget::RightTrigger => state.right_trigger = 1.0,
+        OutputTarget::Button(button) => state.buttons.set(button, true),
+        OutputTarget::JoystickAxis(index) | OutputTarget::JoystickPedal(index) => {
+            if let Some(axis) = state.joystick.axes.get_mut(index) {
+                *axis = 1.0;
+            }
+        }
+        OutputTarget::JoystickButton(index) => state.joystick.press_button(index),
+        OutputTarget::JoystickHat { hat, direction } => state.joystick.press_hat(hat, direction),
+        OutputTarget::Key(code) => state.keyboard_mouse.press_key(code),
+        OutputTarget::MouseX => state.keyboard_mouse.mouse_x = 1.0,
+        OutputTarget::MouseY => state.keyboard_mouse.mouse_y = 1.0,
+        OutputTarget::MouseButton(button) => state.keyboard_mouse.mouse_buttons[button as usize] = true,
+    }
+}
+
+/// Build the gamepad state from every binding. When several sources drive the
+/// same target the biggest deflection wins and buttons are OR'd together.
+pub fn apply_bindings(bindings: &[Binding], input: &InputState) -> XboxControllerState {
+    let mut state = XboxControllerState::default();
+    // [positive, negative] for each stick axis driven by halves
+    let mut halves = [[0.0f32; 2]; StickAxis::ALL.len()];
+    // Joystick axes driven as pedals, None where nothing is
+    let mut pedals = [None::<f32>; MAX_JOYSTICK_AXES];
+
+    for binding in bindings {
+        match binding.target {
+            OutputTarget::LeftStickX => merge_stick(&mut state.left_stick_x, binding.source.read_stick(input)),
+            OutputTarget::LeftStickY => merge_stick(&mut state.left_stick_y, binding.source.read_stick(input)),
+            OutputTarget::RightStickX => merge_stick(&mut state.right_stick_x, binding.source.read_stick(input)),
+            OutputTarget::RightStickY => merge_stick(&mut state.right_stick_y, binding.source.read_stick(input)),
+            OutputTarget::StickHalf { axis, positive } => {
+                let half = &mut halves[axis as usize][if positive { 0 } else { 1 }];
+                merge_trigger(half, binding.source.read_trigger(input));
+            }
+            OutputTarget::LeftTrigger => merge_trigger(&mut state.left_trigger, binding.source.read_trigger(input)),
+            OutputTarget::RightTrigger => merge_trigger(&mut state.right_trigger, binding.source.read_trigger(input)),
+            OutputTarget::Button(button) => {
+                if binding.source.read_pressed(input).unwrap_or(false) {
+                    state.buttons.set(button, true);
+                }
+            }
+            OutputTarget::JoystickAxis(index) => {
+                if let Some(axis) = state.joystick.axes.get_mut(index) {
+                    merge_stick(axis, binding.source.read_stick(input));
+                }
+            }
+            OutputTarget::JoystickPedal(index) => {
+                if let (Some(pedal), Some(value)) = (pedals.get_mut(index), binding.source.read_trigger(input)) {
+                    merge_trigger(pedal.get_or_insert(0.0), Some(value));
+                }
+            }
+            OutputTarget::JoystickButton(index) => {
+                if binding.source.read_pressed(input).unwrap_or(false) {
+                    state.joystick.press_button(index);
+                }
+            }
+            OutputTarget::JoystickHat { hat, direction } => {
+                if binding.source.read_pressed(input).unwrap_or(false) {
+                    state.joystick.press_hat(hat, direction);
+                }
+            }
+            OutputTarget::Key(code) => {
+                if binding.source.read_pressed(input).unwrap_or(false) {
+                    state.keyboard_mouse.press_key(code);
+                }
+            }
+            OutputTarget::MouseX => merge_stick(&mut state.keyboard_mouse.mouse_x, binding.source.read_stick(input)),
+            OutputTarget::MouseY => merge_stick(&mut state.keyboard_mouse.mouse_y, binding.source.read_stick(input)),
+            OutputTarget::MouseButton(button) => {
+                if binding.source.read_pressed(input).unwrap_or(false) {
+                    state.keyboard_mouse.mouse_buttons[button as usize] = true;
+                }
+            }
+        }
+    }
+
+    for (axis, pedal) in state.joystick.axes.iter_mut().zip(pedals) {
+        if let Some(pedal) = pedal {
+            merge_stick(axis, Some(pedal * 2.0 - 1.0));
+        }
+    }
+
+    for axis in StickAxis::ALL {
+        let [positive, negative] = halves[axis as usize];
+        merge_stick(axis.value_mut(&mut state), Some(positive - negative));
+    }
+
+    state
+}
+
+fn merge_stick(out: &mut f32, value: Option<f32>) {
+    if let Some(value) = value {
+        if value.abs() > out.abs() {
+            *out = value;
+        }
+    }
+}
+
+fn merge_trigger(out: &mut f32, value: Option<f32>) {
+    if let Some(value) = value {
+        *out = out.max(value);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn is_hat_axis(axis_code: u32) -> bool {
+    // ABS_HAT0X..ABS_HAT3Y
+    (0x10..=0x17).contains(&axis_code)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_hat_axis(_axis_code: u32) -> bool {
+    false
+}
+
+#[derive(Debug, Clone)]
+struct AxisRange {
+    device_name: String,
+    initial: f32,
+    min: f32,
+    max: f32,
+}
+
+impl AxisRange {
+    fn movement(&self) -> f32 {
+        (self.max - self.initial).abs().max((self.min - self.initial).abs())
+    }
+}
+
+/// Listens for the next input the user touches to bind it. Buttons and hats are
+/// captured right away, axes are swept end to end and captured on `finish_axis`.
+#[derive(Debug, Default)]
+pub struct SourceCapture {
+    axes: HashMap<(String, u32), AxisRange>,
+    captured: Option<InputSource>,
+}
+
+impl SourceCapture {
+    pub fn process_event(&mut self, event: &InputEvent) {
+        if self.captured.is_some() {
+            return;
+        }
+
+        match event {
+            InputEvent::AxisMoved { device_id, device_name, axis_code, value } => {
+                if is_hat_axis(*axis_code) && value.abs() > 0.5 {
+                    self.captured = Some(InputSource::Hat(HatBinding {
+                        device_id: device_id.clone(),
+                        device_name: device_name.clone(),
+                        identity: None,
+                        axis_code: *axis_code,
+                        positive: *value > 0.0,
+                    }));
+                    return;
+                }
+
+                let range = self.axes.entry((device_id.clone(), *axis_code)).or_insert(AxisRange {
+                    device_name: device_name.clone(),
+                    initial: *value,
+                    min: *value,
+                    max: *value,
+                });
+                range.min = range.min.min(*value);
+                range.max = range.max.max(*value);
+            }
+            InputEvent::ButtonPressed { device_id, device_name, button_code } => {
+                self.captured = Some(InputSource::Button(ButtonBinding {
+                    device_id: device_id.clone(),
+                    device_name: device_name.clone(),
+                    identity: None,
+                    button_code: *button_code,
+                }));
+            }
+            _ => {}
+        }
+    }
+
+    /// A button or hat that was pressed, if any
+    pub fn captured(&self) -> Option<&InputSource> {
+        self.captured.as_ref()
+    }
+
+    pub fn describe(&self) -> Option<String> {
+        if let Some(ref source) = self.captured {
+            return Some(source.describe());
+        }
+        self.most_moved_axis().map(|((_, code), range)| {
+            format!("{} - Axis {} (range {:.2} to {:.2})", range.device_name, code, range.min, range.max)
+        })
+    }
+
+    fn most_moved_axis(&self) -> Option<(&(String, u32), &AxisRange)> {
+        self.axes
+            .iter()
+            .max_by(|a, b| a.1.movement().total_cmp(&b.1.movement()))
+            .filter(|(_, range)| range.movement() > 0.1)
+    }
+
+    /// Take whatever was captured, calibrating an axis to the range it was swept through
+    pub fn finish(self) -> Option<InputSource> {
+        if self.captured.is_some() {
+            return self.captured;
+        }
+
+        self.most_moved_axis().map(|((device_id, axis_code), range)| {
+            InputSource::Axis(AxisBinding {
+                device_id: device_id.clone(),
+                device_name: range.device_name.clone(),
+                identity: None,
+                axis_code: *axis_code,
+                min_value: range.min,
+                max_value: range.max,
+                inverted: false,
+                curve: ResponseCurve::default(),
+                rotation: None,
+                split: None,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AxisHalf, AxisSplit};
+
+    const AXIS: u32 = 1;
+
+    /// One half of a -1..1 axis split at 0, like the wizard leaves a shared pedal axis
+    fn pedal(label: &str, half: AxisHalf, target: OutputTarget) -> Binding {
+        Binding {
+            label: label.to_string(),
+            role: None,
+            source: InputSource::Axis(AxisBinding {
+                device_id: "0".to_string(),
+                device_name: "Wheel".to_string(),
+                identity: None,
+                axis_code: AXIS,
+                min_value: -1.0,
+                max_value: 1.0,
+                inverted: false,
+                curve: ResponseCurve::default(),
+                rotation: None,
+                split: Some(AxisSplit { center: 0.0, half }),
+            }),
+            target,
+        }
+    }
+
+    fn axis_at(value: f32) -> InputState {
+        let mut input = InputState::default();
+        input.axes.insert("0".to_string(), HashMap::from([(AXIS, value)]));
+        input
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 0.01, "expected {expected}, got {actual}");
+    }
+
+    #[test]
+    fn stick_halves_combine_into_one_axis() {
+        let bindings = [
+            pedal("Throttle", AxisHalf::Above, OutputTarget::StickHalf { axis: StickAxis::LeftY, positive: true }),
+            pedal("Brake", AxisHalf::Below, OutputTarget::StickHalf { axis: StickAxis::LeftY, positive: false }),
+        ];
+
+        assert_near(apply_bindings(&bindings, &axis_at(0.0)).left_stick_y, 0.0);
+        assert_near(apply_bindings(&bindings, &axis_at(1.0)).left_stick_y, 1.0);
+        assert_near(apply_bindings(&bindings, &axis_at(-0.5)).left_stick_y, -0.5);
+    }
+
+    #[test]
+    fn joystick_pedals_rest_at_minus_one() {
+        let bindings = [
+            pedal("Throttle", AxisHalf::Above, OutputTarget::JoystickPedal(1)),
+            pedal("Brake", AxisHalf::Below, OutputTarget::JoystickPedal(2)),
+        ];
+
+        let released = apply_bindings(&bindings, &axis_at(0.0)).joystick.axes;
+        assert_near(released[1], -1.0);
+        assert_near(released[2], -1.0);
+
+        let throttle = apply_bindings(&bindings, &axis_at(1.0)).joystick.axes;
+        assert_near(throttle[1], 1.0);
+        assert_near(throttle[2], -1.0);
+
+        let half_brake = apply_bindings(&bindings, &axis_at(-0.5)).joystick.axes;
+        assert_near(half_brake[1], -1.0);
+        assert_near(half_brake[2], 0.0);
+
+        // Axes nothing drives stay centered
+        assert_near(released[0], 0.0);
+    }
+
+    #[test]
+    fn joystick_pedal_takes_the_furthest_pressed_source() {
+        let button = Binding {
+            label: "Throttle button".to_string(),
+            role: None,
+            source: InputSource::Button(ButtonBinding {
+                device_id: "0".to_string(),
+                device_name: "Wheel".to_string(),
+                identity: None,
+                button_code: 5,
+            }),
+            target: OutputTarget::JoystickPedal(1),
+        };
+        let bindings = [pedal("Throttle", AxisHalf::Above, OutputTarget::JoystickPedal(1)), button];
+
+        let mut input = axis_at(0.5);
+        input.buttons.insert("0".to_string(), HashMap::from([(5, false)]));
+        assert_near(apply_bindings(&bindings, &input).joystick.axes[1], 0.0);
+
+        input.buttons.insert("0".to_string(), HashMap::from([(5, true)]));
+        assert_near(apply_bindings(&bindings, &input).joystick.axes[1], 1.0);
+    }
+}
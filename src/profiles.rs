@@ -0,0 +1,187 @@
+use crate::config::{self, ConfigError, Loaded, WheelConfig, CONFIG_VERSION, DEFAULT_PROFILE_NAME};
+use crate::engine::EngineSettings;
+use crate::input::InputDevice;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    pub config: WheelConfig,
+}
+
+/// Every wheel setup saved on this machine, stored together in the config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfileStore {
+    pub schema_version: u32,
+    pub active_profile: Option<String>,
+    pub profiles: Vec<Profile>,
+    /// Profiles that each run their own gamepad next to the active one, for a second
+    /// wheel or a handbrake box that should show up as another pad
+    pub extra_pads: Vec<String>,
+    pub engine: EngineSettings,
+}
+
+impl Default for ProfileStore {
+    fn default() -> Self {
+        Self {
+            schema_version: CONFIG_VERSION,
+            active_profile: None,
+            profiles: Vec::new(),
+            extra_pads: Vec::new(),
+            engine: EngineSettings::default(),
+        }
+    }
+}
+
+impl ProfileStore {
+    pub fn load() -> Result<Option<Loaded<Self>>, ConfigError> {
+        config::load()
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        config::save(self)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut Profile> {
+        self.profiles.iter_mut().find(|p| p.name == name)
+    }
+
+    pub fn active(&self) -> Option<&Profile> {
+        self.active_profile.as_deref().and_then(|name| self.get(name))
+    }
+
+    pub fn active_mut(&mut self) -> Option<&mut Profile> {
+        let name = self.active_profile.clone()?;
+        self.get_mut(&name)
+    }
+
+    /// The extra pad profiles that will actually run, the active one already has its pad
+    pub fn running_extra_pads(&self) -> Vec<&Profile> {
+        self.extra_pads
+            .iter()
+            .filter(|name| self.active_profile.as_ref() != Some(*name))
+            .filter_map(|name| self.get(name))
+            .collect()
+    }
+
+    pub fn set_extra_pad(&mut self, name: &str, enabled: bool) {
+        self.extra_pads.retain(|n| n != name);
+        if enabled {
+            self.extra_pads.push(name.to_string());
+        }
+    }
+
+    pub fn select(&mut self, name: &str) -> anyhow::Result<()> {
+        if self.get(name).is_none() {
+            return Err(anyhow::anyhow!("No profile named {}", name));
+        }
+        self.active_profile = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Add an empty profile and make it active
+    pub fn create(&mut self, name: &str) -> anyhow::Result<()> {
+        self.insert(Profile {
+            name: name.to_string(),
+            config: WheelConfig::default(),
+        })
+    }
+
+    /// Copy a profile under a new name and make the copy active
+    pub fn duplicate(&mut self, name: &str, new_name: &str) -> anyhow::Result<()> {
+        let config = self.get(name)
+            .ok_or_else(|| anyhow::anyhow!("No profile named {}", name))?
+            .config
+            .clone();
+        self.insert(Profile {
+            name: new_name.to_string(),
+            config,
+        })
+    }
+
+    pub fn rename(&mut self, name: &str, new_name: &str) -> anyhow::Result<()> {
+        let new_name = Self::validate_name(new_name)?;
+        if name == new_name {
+            return Ok(());
+        }
+        if self.get(new_name).is_some() {
+            return Err(anyhow::anyhow!("A profile named {} already exists", new_name));
+        }
+
+        let profile = self.get_mut(name)
+            .ok_or_else(|| anyhow::anyhow!("No profile named {}", name))?;
+        profile.name = new_name.to_string();
+
+        for extra in self.extra_pads.iter_mut().filter(|n| *n == name) {
+            *extra = new_name.to_string();
+        }
+        if self.active_profile.as_deref() == Some(name) {
+            self.active_profile = Some(new_name.to_string());
+        }
+        Ok(())
+    }
+
+    /// Remove a profile, the first remaining one becomes active if it was the active one
+    pub fn delete(&mut self, name: &str) -> anyhow::Result<()> {
+        let index = self.profiles.iter().position(|p| p.name == name)
+            .ok_or_else(|| anyhow::anyhow!("No profile named {}", name))?;
+        self.profiles.remove(index);
+        self.extra_pads.retain(|n| n != name);
+
+        if self.active_profile.as_deref() == Some(name) {
+            self.active_profile = self.profiles.first().map(|p| p.name.clone());
+        }
+        Ok(())
+    }
+
+    /// `base`, or `base (2)`, `base (3)`... if that's taken
+    pub fn unique_name(&self, base: &str) -> String {
+        let base = base.trim();
+        let base = if base.is_empty() { DEFAULT_PROFILE_NAME } else { base };
+        if self.get(base).is_none() {
+            return base.to_string();
+        }
+        (2..)
+            .map(|n| format!("{} ({})", base, n))
+            .find(|name| self.get(name).is_none())
+            .unwrap_or_default()
+    }
+
+    /// The complete profile with the most bindings whose devices are all connected
+    pub fn best_match(&self, devices: &HashMap<String, InputDevice>) -> Option<&str> {
+        self.profiles
+            .iter()
+            .filter(|p| p.config.is_complete())
+            .filter_map(|p| {
+                let (connected, total) = p.config.connected_bindings(devices);
+                (total > 0 && connected == total).then_some((total, p))
+            })
+            // The active profile wins ties so plugging in a device doesn't flip between equal setups
+            .max_by_key(|(total, p)| (*total, self.active_profile.as_deref() == Some(p.name.as_str())))
+            .map(|(_, p)| p.name.as_str())
+    }
+
+    fn insert(&mut self, mut profile: Profile) -> anyhow::Result<()> {
+        profile.name = Self::validate_name(&profile.name)?.to_string();
+        if self.get(&profile.name).is_some() {
+            return Err(anyhow::anyhow!("A profile named {} already exists", profile.name));
+        }
+        self.active_profile = Some(profile.name.clone());
+        self.profiles.push(profile);
+        Ok(())
+    }
+
+    fn validate_name(name: &str) -> anyhow::Result<&str> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(anyhow::anyhow!("Profile name can't be empty"));
+        }
+        Ok(name)
+    }
+}
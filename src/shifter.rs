@@ -0,0 +1,315 @@
+use crate::input::{DeviceIdentity, InputState};
+use crate::mapping::InputSource;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+pub const REVERSE: i32 = -1;
+pub const NEUTRAL: i32 = 0;
+
+/// How long the lever has to sit in neutral before it counts, it passes through on every throw
+const NEUTRAL_DWELL: Duration = Duration::from_millis(500);
+
+pub fn gear_name(gear: i32) -> String {
+    match gear {
+        REVERSE => "R".to_string(),
+        NEUTRAL => "N".to_string(),
+        gear => gear.to_string(),
+    }
+}
+
+/// H-pattern shifter emulated with the sequential shift up/down buttons, since gamepads have no gears
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HShifterConfig {
+    pub enabled: bool,
+    /// Input for each forward gear, first gear first. Nothing selected means neutral.
+    pub gears: Vec<Option<InputSource>>,
+    pub reverse: Option<InputSource>,
+    /// Wheel button that triggers a resync
+    pub resync: Option<InputSource>,
+    /// How long each shift press is held
+    pub pulse_ms: u64,
+    /// Pause after each press so the game counts them separately
+    pub gap_ms: u64,
+    /// Lowest gear the game goes down to, where a resync leaves it and where the game starts
+    pub lowest_gear: i32,
+}
+
+impl Default for HShifterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            gears: vec![None; 6],
+            reverse: None,
+            resync: None,
+            pulse_ms: 60,
+            gap_ms: 60,
+            lowest_gear: 1,
+        }
+    }
+}
+
+impl HShifterConfig {
+    /// Gear the shifter is physically in right now
+    pub fn selected_gear(&self, input: &InputState) -> i32 {
+        let pressed = |source: &Option<InputSource>| {
+            source.as_ref().and_then(|s| s.read_pressed(input)).unwrap_or(false)
+        };
+
+        if pressed(&self.reverse) {
+            return REVERSE;
+        }
+        self.gears
+            .iter()
+            .position(pressed)
+            .map(|i| i as i32 + 1)
+            .unwrap_or(NEUTRAL)
+    }
+
+    pub fn sources(&self) -> impl Iterator<Item = &InputSource> {
+        self.gears.iter().chain([&self.reverse, &self.resync]).flatten()
+    }
+
+    pub fn devices_mut(&mut self) -> impl Iterator<Item = (&mut String, &str, &mut Option<DeviceIdentity>)> {
+        self.gears
+            .iter_mut()
+            .chain([&mut self.reverse, &mut self.resync])
+            .flatten()
+            .map(|source| source.device_mut())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftOutput {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone)]
+struct Pulse {
+    direction: ShiftOutput,
+    /// false once the press is over and we're waiting out the gap
+    pressed: bool,
+    until: Instant,
+}
+
+/// Tracks which gear the game should be in and sends shift presses to get it there
+#[derive(Debug)]
+pub struct HShifter {
+    current_gear: i32,
+    /// When the lever went into neutral
+    neutral_since: Option<Instant>,
+    pulse: Option<Pulse>,
+    /// Shift downs left in a resync
+    resync_remaining: u32,
+    resync_held: bool,
+}
+
+impl HShifter {
+    /// Starts out in the game's lowest gear, the one it starts in
+    pub fn new(config: &HShifterConfig) -> Self {
+        Self {
+            current_gear: config.lowest_gear,
+            neutral_since: None,
+            pulse: None,
+            resync_remaining: 0,
+            resync_held: false,
+        }
+    }
+
+    /// Gear the game should be in, as far as we know
+    pub fn current_gear(&self) -> i32 {
+        self.current_gear
+    }
+
+    pub fn is_resyncing(&self) -> bool {
+        self.resync_remaining > 0
+    }
+
+    /// Shift down enough times to reach the lowest gear from the top one, so our gear matches the game's again
+    pub fn resync(&mut self, config: &HShifterConfig) {
+        self.resync_remaining = (config.gears.len() as i32 - config.lowest_gear).max(0) as u32;
+        if self.resync_remaining == 0 {
+            self.current_gear = config.lowest_gear;
+        }
+        log::info!("Resyncing H-shifter, sending {} shift downs", self.resync_remaining);
+    }
+
+    /// Which shift button to hold this frame, if any
+    pub fn update(&mut self, config: &HShifterConfig, input: &InputState, now: Instant) -> Option<ShiftOutput> {
+        let resync_pressed = config.resync
+            .as_ref()
+            .and_then(|s| s.read_pressed(input))
+            .unwrap_or(false);
+        if resync_pressed && !self.resync_held {
+            self.resync(config);
+        }
+        self.resync_held = resync_pressed;
+
+        let selected = config.selected_gear(input);
+        let target = if selected == NEUTRAL {
+            // Only games with a neutral get shifted into it, and only once the lever stays there
+            let since = *self.neutral_since.get_or_insert(now);
+            if config.lowest_gear <= NEUTRAL && now.duration_since(since) >= NEUTRAL_DWELL {
+                NEUTRAL
+            } else {
+                self.current_gear
+            }
+        } else {
+            self.neutral_since = None;
+            selected.max(config.lowest_gear)
+        };
+
+        if let Some(ref mut pulse) = self.pulse {
+            if now < pulse.until {
+                return pulse.pressed.then_some(pulse.direction);
+            }
+            if pulse.pressed {
+                pulse.pressed = false;
+                pulse.until = now + Duration::from_millis(config.gap_ms);
+                return None;
+            }
+            self.pulse = None;
+        }
+
+        let direction = if self.resync_remaining > 0 {
+            self.resync_remaining -= 1;
+            if self.resync_remaining == 0 {
+                self.current_gear = config.lowest_gear;
+            }
+            ShiftOutput::Down
+        } else {
+            if target > self.current_gear {
+                self.current_gear += 1;
+                ShiftOutput::Up
+            } else if target < self.current_gear {
+                self.current_gear -= 1;
+                ShiftOutput::Down
+            } else {
+                return None;
+            }
+        };
+
+        self.pulse = Some(Pulse {
+            direction,
+            pressed: true,
+            until: now + Duration::from_millis(config.pulse_ms),
+        });
+        Some(direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ButtonBinding;
+
+    const REVERSE_BUTTON: u32 = 10;
+
+    fn button(code: u32) -> Option<InputSource> {
+        Some(InputSource::Button(ButtonBinding {
+            device_id: "0".to_string(),
+            device_name: "Shifter".to_string(),
+            identity: None,
+            button_code: code,
+        }))
+    }
+
+    /// Gears 1 to 6 on buttons 1 to 6, reverse on `REVERSE_BUTTON`
+    fn config(lowest_gear: i32) -> HShifterConfig {
+        HShifterConfig {
+            enabled: true,
+            gears: (1..=6).map(button).collect(),
+            reverse: button(REVERSE_BUTTON),
+            lowest_gear,
+            ..Default::default()
+        }
+    }
+
+    /// The lever in `gear`, or neutral with nothing pressed
+    fn lever(gear: i32) -> InputState {
+        let mut input = InputState::default();
+        let code = match gear {
+            REVERSE => Some(REVERSE_BUTTON),
+            NEUTRAL => None,
+            gear => Some(gear as u32),
+        };
+        input.buttons.insert("0".to_string(), code.map(|code| (code, true)).into_iter().collect());
+        input
+    }
+
+    /// Step `update` every 10ms over `from_ms..to_ms`, returning each press sent
+    fn run(
+        shifter: &mut HShifter,
+        config: &HShifterConfig,
+        input: &InputState,
+        start: Instant,
+        from_ms: u64,
+        to_ms: u64,
+    ) -> Vec<ShiftOutput> {
+        let mut presses = Vec::new();
+        let mut held = None;
+        for ms in (from_ms..to_ms).step_by(10) {
+            let output = shifter.update(config, input, start + Duration::from_millis(ms));
+            if let (Some(direction), None) = (output, held) {
+                presses.push(direction);
+            }
+            held = output;
+        }
+        presses
+    }
+
+    #[test]
+    fn starts_in_neutral_without_shifting() {
+        let config = config(1);
+        let mut shifter = HShifter::new(&config);
+        let start = Instant::now();
+
+        assert!(run(&mut shifter, &config, &lever(NEUTRAL), start, 0, 2000).is_empty());
+        assert_eq!(shifter.current_gear(), 1);
+    }
+
+    #[test]
+    fn throw_through_neutral_only_shifts_up() {
+        let config = config(1);
+        let mut shifter = HShifter::new(&config);
+        let start = Instant::now();
+
+        assert_eq!(run(&mut shifter, &config, &lever(3), start, 0, 500), [ShiftOutput::Up, ShiftOutput::Up]);
+        assert_eq!(shifter.current_gear(), 3);
+
+        // Crossing the gate is quicker than the dwell
+        assert!(run(&mut shifter, &config, &lever(NEUTRAL), start, 500, 700).is_empty());
+        assert_eq!(run(&mut shifter, &config, &lever(4), start, 700, 1000), [ShiftOutput::Up]);
+        assert_eq!(shifter.current_gear(), 4);
+    }
+
+    #[test]
+    fn neutral_counts_after_dwell_when_game_has_one() {
+        let config = config(NEUTRAL);
+        let mut shifter = HShifter::new(&config);
+        let start = Instant::now();
+
+        assert_eq!(run(&mut shifter, &config, &lever(1), start, 0, 200), [ShiftOutput::Up]);
+        assert!(run(&mut shifter, &config, &lever(NEUTRAL), start, 200, 600).is_empty());
+        assert_eq!(run(&mut shifter, &config, &lever(NEUTRAL), start, 600, 1000), [ShiftOutput::Down]);
+        assert_eq!(shifter.current_gear(), NEUTRAL);
+    }
+
+    #[test]
+    fn resync_shifts_down_from_top_gear_to_lowest() {
+        for (lowest_gear, shifts) in [(REVERSE, 7), (NEUTRAL, 6), (1, 5)] {
+            let config = config(lowest_gear);
+            let mut shifter = HShifter::new(&config);
+            let start = Instant::now();
+
+            // Just long enough for the presses and their gaps, before the lever counts as neutral again
+            shifter.resync(&config);
+            let duration = shifts as u64 * (config.pulse_ms + config.gap_ms);
+            let presses = run(&mut shifter, &config, &lever(NEUTRAL), start, 0, duration);
+            assert_eq!(presses, vec![ShiftOutput::Down; shifts], "lowest gear {}", lowest_gear);
+            assert_eq!(shifter.current_gear(), lowest_gear);
+            assert!(!shifter.is_resyncing());
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use super::linux::{gamepad_ff, GamepadFf};
+use super::uinput::{AbsAxis, DeviceSpec, UinputDevice};
+use super::uinput_ffi::*;
+use super::{FfPassthrough, RumbleState, VirtualController, XboxControllerState};
+use std::path::Path;
+
+// Same ids as a wired DualShock 4 v2, which is what games and Steam Input look for
+const DS4_NAME: &str = "Sony Interactive Entertainment Wireless Controller";
+const DS4_VENDOR: u16 = 0x054c;
+const DS4_PRODUCT: u16 = 0x09cc;
+const DS4_VERSION: u16 = 0x8111;
+
+const STICK_MAX: i32 = 255;
+/// Accelerometer counts per g and gyro counts per degree per second, as the kernel driver reports them
+const ACCEL_RES_PER_G: i32 = 8192;
+const GYRO_RES_PER_DEG_S: i32 = 1024;
+const TOUCHPAD_WIDTH: i32 = 1920;
+const TOUCHPAD_HEIGHT: i32 = 942;
+
+const DS4_KEYS: &[u16] = &[BTN_SOUTH, BTN_EAST, BTN_NORTH, BTN_WEST, BTN_TL, BTN_TR, BTN_TL2, BTN_TR2,
+                           BTN_SELECT, BTN_START, BTN_MODE, BTN_THUMBL, BTN_THUMBR];
+
+const DS4_AXES: &[AbsAxis] = &[
+    AbsAxis::new(ABS_X, 0, STICK_MAX),
+    AbsAxis::new(ABS_Y, 0, STICK_MAX),
+    AbsAxis::new(ABS_RX, 0, STICK_MAX),
+    AbsAxis::new(ABS_RY, 0, STICK_MAX),
+    AbsAxis::new(ABS_Z, TRIGGER_MIN, TRIGGER_MAX),
+    AbsAxis::new(ABS_RZ, TRIGGER_MIN, TRIGGER_MAX),
+    AbsAxis::new(ABS_HAT0X, -1, 1),
+    AbsAxis::new(ABS_HAT0Y, -1, 1),
+];
+
+const MOTION_AXES: &[AbsAxis] = &[
+    AbsAxis::new(ABS_X, -4 * ACCEL_RES_PER_G, 4 * ACCEL_RES_PER_G).with_resolution(ACCEL_RES_PER_G),
+    AbsAxis::new(ABS_Y, -4 * ACCEL_RES_PER_G, 4 * ACCEL_RES_PER_G).with_resolution(ACCEL_RES_PER_G),
+    AbsAxis::new(ABS_Z, -4 * ACCEL_RES_PER_G, 4 * ACCEL_RES_PER_G).with_resolution(ACCEL_RES_PER_G),
+    AbsAxis::new(ABS_RX, -2048 * GYRO_RES_PER_DEG_S, 2048 * GYRO_RES_PER_DEG_S).with_resolution(GYRO_RES_PER_DEG_S),
+    AbsAxis::new(ABS_RY, -2048 * GYRO_RES_PER_DEG_S, 2048 * GYRO_RES_PER_DEG_S).with_resolution(GYRO_RES_PER_DEG_S),
+    AbsAxis::new(ABS_RZ, -2048 * GYRO_RES_PER_DEG_S, 2048 * GYRO_RES_PER_DEG_S).with_resolution(GYRO_RES_PER_DEG_S),
+];
+
+const TOUCHPAD_KEYS: &[u16] = &[BTN_LEFT, BTN_TOUCH, BTN_TOOL_FINGER];
+
+const TOUCHPAD_AXES: &[AbsAxis] = &[
+    AbsAxis::new(ABS_X, 0, TOUCHPAD_WIDTH - 1),
+    AbsAxis::new(ABS_Y, 0, TOUCHPAD_HEIGHT - 1),
+    AbsAxis::new(ABS_MT_SLOT, 0, 1),
+    AbsAxis::new(ABS_MT_POSITION_X, 0, TOUCHPAD_WIDTH - 1),
+    AbsAxis::new(ABS_MT_POSITION_Y, 0, TOUCHPAD_HEIGHT - 1),
+    AbsAxis::new(ABS_MT_TRACKING_ID, 0, u16::MAX as i32),
+];
+
+/// A DualShock 4 made of the same three devices the kernel's playstation driver
+/// creates: the gamepad, its motion sensors and its touchpad
+pub struct VirtualDualShock4 {
+    // Declared first so the FF thread stops before the gamepad is destroyed
+    ff: GamepadFf,
+    gamepad: UinputDevice,
+    /// Kept alive so the sensors are there for games that look for them, a wheel doesn't move
+    _motion: UinputDevice,
+    touchpad: UinputDevice,
+    touchpad_pressed: bool,
+    /// Each touch gets a new tracking id, like a real finger would
+    touch_id: i32,
+    connected: bool,
+}
+
+impl VirtualDualShock4 {
+    pub fn new(passthrough: &[u16]) -> anyhow::Result<Self> {
+        let gamepad = UinputDevice::create(&DeviceSpec {
+            name: DS4_NAME,
+            vendor: DS4_VENDOR,
+            product: DS4_PRODUCT,
+            version: DS4_VERSION,
+            keys: DS4_KEYS,
+            rel: &[],
+            axes: DS4_AXES,
+            misc: &[],
+            props: &[],
+            ff: &gamepad_ff(passthrough),
+        })?;
+
+        let motion_name = format!("{} Motion Sensors", DS4_NAME);
+        let mut motion = UinputDevice::create(&DeviceSpec {
+            name: &motion_name,
+            vendor: DS4_VENDOR,
+            product: DS4_PRODUCT,
+            version: DS4_VERSION,
+            keys: &[],
+            rel: &[],
+            axes: MOTION_AXES,
+            misc: &[MSC_TIMESTAMP],
+            props: &[INPUT_PROP_ACCELEROMETER],
+            ff: &[],
+        })?;
+        // Lying flat and still, gravity pulls along Y
+        motion.write_event(EV_ABS, ABS_Y, ACCEL_RES_PER_G)?;
+        motion.sync()?;
+
+        let touchpad_name = format!("{} Touchpad", DS4_NAME);
+        let touchpad = UinputDevice::create(&DeviceSpec {
+            name: &touchpad_name,
+            vendor: DS4_VENDOR,
+            product: DS4_PRODUCT,
+            version: DS4_VERSION,
+            keys: TOUCHPAD_KEYS,
+            rel: &[],
+            axes: TOUCHPAD_AXES,
+            misc: &[],
+            props: &[INPUT_PROP_POINTER, INPUT_PROP_BUTTONPAD],
+            ff: &[],
+        })?;
+
+        let ff = GamepadFf::start(gamepad.fd(), passthrough)?;
+
+        log::info!("Uinput DualShock 4 created");
+
+        Ok(Self {
+            ff,
+            gamepad,
+            _motion: motion,
+            touchpad,
+            touchpad_pressed: false,
+            touch_id: 0,
+            connected: true,
+        })
+    }
+
+    /// A click is a finger pressing down in the middle of the pad
+    fn update_touchpad(&mut self, pressed: bool) -> anyhow::Result<()> {
+        if pressed == self.touchpad_pressed {
+            return Ok(());
+        }
+        self.touchpad_pressed = pressed;
+
+        let pad = &mut self.touchpad;
+        pad.write_event(EV_ABS, ABS_MT_SLOT, 0)?;
+        if pressed {
+            self.touch_id = (self.touch_id + 1) & u16::MAX as i32;
+            pad.write_event(EV_ABS, ABS_MT_TRACKING_ID, self.touch_id)?;
+            pad.write_event(EV_ABS, ABS_MT_POSITION_X, TOUCHPAD_WIDTH / 2)?;
+            pad.write_event(EV_ABS, ABS_MT_POSITION_Y, TOUCHPAD_HEIGHT / 2)?;
+            pad.write_event(EV_ABS, ABS_X, TOUCHPAD_WIDTH / 2)?;
+            pad.write_event(EV_ABS, ABS_Y, TOUCHPAD_HEIGHT / 2)?;
+        } else {
+            pad.write_event(EV_ABS, ABS_MT_TRACKING_ID, -1)?;
+        }
+        pad.write_event(EV_KEY, BTN_TOUCH, pressed as i32)?;
+        pad.write_event(EV_KEY, BTN_TOOL_FINGER, pressed as i32)?;
+        pad.write_event(EV_KEY, BTN_LEFT, pressed as i32)?;
+        pad.sync()
+    }
+}
+
+/// -1..1 stick value to the DS4's 0..255 with 128 at rest
+fn stick(value: f32) -> i32 {
+    (((value.clamp(-1.0, 1.0) + 1.0) / 2.0) * STICK_MAX as f32).round() as i32
+}
+
+impl VirtualController for VirtualDualShock4 {
+    fn update(&mut self, state: &XboxControllerState) -> anyhow::Result<()> {
+        if !self.connected {
+            return Ok(());
+        }
+
+        let lt = (state.left_trigger * TRIGGER_MAX as f32) as i32;
+        let rt = (state.right_trigger * TRIGGER_MAX as f32) as i32;
+        let buttons = &state.buttons;
+
+        let pad = &mut self.gamepad;
+        pad.write_event(EV_ABS, ABS_X, stick(state.left_stick_x))?;
+        pad.write_event(EV_ABS, ABS_Y, stick(state.left_stick_y))?;
+        pad.write_event(EV_ABS, ABS_RX, stick(state.right_stick_x))?;
+        pad.write_event(EV_ABS, ABS_RY, stick(state.right_stick_y))?;
+        pad.write_event(EV_ABS, ABS_Z, lt)?;
+        pad.write_event(EV_ABS, ABS_RZ, rt)?;
+
+        // Xbox buttons by position: A is cross, B circle, X square and Y triangle
+        pad.write_event(EV_KEY, BTN_SOUTH, buttons.a as i32)?;
+        pad.write_event(EV_KEY, BTN_EAST, buttons.b as i32)?;
+        pad.write_event(EV_KEY, BTN_WEST, buttons.x as i32)?;
+        pad.write_event(EV_KEY, BTN_NORTH, buttons.y as i32)?;
+        pad.write_event(EV_KEY, BTN_TL, buttons.left_bumper as i32)?;
+        pad.write_event(EV_KEY, BTN_TR, buttons.right_bumper as i32)?;
+        // L2 and R2 click as soon as they move, like the real triggers
+        pad.write_event(EV_KEY, BTN_TL2, (lt > 0) as i32)?;
+        pad.write_event(EV_KEY, BTN_TR2, (rt > 0) as i32)?;
+        pad.write_event(EV_KEY, BTN_SELECT, buttons.back as i32)?;
+        pad.write_event(EV_KEY, BTN_START, buttons.start as i32)?;
+        pad.write_event(EV_KEY, BTN_MODE, buttons.guide as i32)?;
+        pad.write_event(EV_KEY, BTN_THUMBL, buttons.left_thumb as i32)?;
+        pad.write_event(EV_KEY, BTN_THUMBR, buttons.right_thumb as i32)?;
+
+        let hat_x = match (buttons.dpad_left, buttons.dpad_right) {
+            (true, false) => -1,
+            (false, true) => 1,
+            _ => 0,
+        };
+        let hat_y = match (buttons.dpad_up, buttons.dpad_down) {
+            (true, false) => -1,
+            (false, true) => 1,
+            _ => 0,
+        };
+        pad.write_event(EV_ABS, ABS_HAT0X, hat_x)?;
+        pad.write_event(EV_ABS, ABS_HAT0Y, hat_y)?;
+        pad.sync()?;
+
+        self.update_touchpad(buttons.touchpad)
+    }
+
+    fn get_rumble(&mut self) -> anyhow::Result<RumbleState> {
+        self.ff.rumble()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    fn take_passthrough(&mut self) -> Vec<FfPassthrough> {
+        self.ff.take_passthrough()
+    }
+
+    fn event_node(&self) -> Option<&Path> {
+        self.gamepad.event_node()
+    }
+}
@@ -0,0 +1,308 @@
+use super::uinput_ffi::*;
+use super::RumbleState;
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy)]
+enum EffectParams {
+    Rumble(FFRumbleEffect),
+    Constant(FFConstantEffect),
+    Periodic(FFPeriodicEffect),
+}
+
+/// An effect the game uploaded to the virtual gamepad
+#[derive(Debug, Clone)]
+struct Effect {
+    params: EffectParams,
+    /// 0 plays until stopped
+    length: Duration,
+    delay: Duration,
+    /// Share of the force that pushes right, from the effect's direction
+    right: f32,
+    playback: Option<Playback>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Playback {
+    started: Instant,
+    /// How many times to play it back to back
+    count: u32,
+}
+
+/// What an effect is doing at one instant
+#[derive(Debug, Clone, Copy, Default)]
+struct EffectOutput {
+    large: f32,
+    small: f32,
+    force: f32,
+}
+
+/// Every effect the game uploaded, played back with its own timing like a real
+/// FF device would, then mixed down into one `RumbleState`
+#[derive(Debug)]
+pub struct EffectMixer {
+    effects: HashMap<i16, Effect>,
+    gain: f32,
+    updated: Option<Instant>,
+}
+
+impl Default for EffectMixer {
+    fn default() -> Self {
+        Self {
+            effects: HashMap::new(),
+            gain: 1.0,
+            updated: None,
+        }
+    }
+}
+
+impl EffectMixer {
+    /// Store an uploaded effect. Returns false for effect types we don't emulate.
+    pub fn upload(&mut self, effect: &FFEffect, now: Instant) -> bool {
+        let params = unsafe {
+            match effect.type_ {
+                FF_RUMBLE => EffectParams::Rumble(effect.params()),
+                FF_CONSTANT => EffectParams::Constant(effect.params()),
+                FF_PERIODIC => EffectParams::Periodic(effect.params()),
+                _ => return false,
+            }
+        };
+
+        // Updating a playing effect changes it in place, it doesn't restart it
+        let playback = self.effects.get(&effect.id).and_then(|e| e.playback);
+        self.effects.insert(effect.id, Effect {
+            params,
+            length: Duration::from_millis(effect.replay_length() as u64),
+            delay: Duration::from_millis(effect.replay_delay() as u64),
+            // Same convention as the constant effect we send the wheel: 0x4000 is fully right
+            right: (effect.direction as f32 / 65536.0 * TAU).sin(),
+            playback,
+        });
+        self.updated = Some(now);
+        log::debug!("Stored FF effect {}: {:?}", effect.id, params);
+        true
+    }
+
+    pub fn erase(&mut self, id: i16, now: Instant) {
+        if self.effects.remove(&id).is_some() {
+            self.updated = Some(now);
+        }
+    }
+
+    /// Play `id` `count` times, a count of 0 stops it
+    pub fn play(&mut self, id: i16, count: i32, now: Instant) {
+        let Some(effect) = self.effects.get_mut(&id) else {
+            log::warn!("EV_FF play event for unknown effect ID {}", id);
+            return;
+        };
+        effect.playback = (count > 0).then_some(Playback { started: now, count: count as u32 });
+        self.updated = Some(now);
+    }
+
+    /// FF_GAIN, 0 to 0xFFFF
+    pub fn set_gain(&mut self, gain: i32, now: Instant) {
+        self.gain = gain.clamp(0, u16::MAX as i32) as f32 / u16::MAX as f32;
+        self.updated = Some(now);
+    }
+
+    /// Everything playing at `now`, mixed together
+    pub fn mix(&mut self, now: Instant) -> RumbleState {
+        let mut total = EffectOutput::default();
+        let mut active = false;
+        for effect in self.effects.values_mut() {
+            let Some(playback) = effect.playback else {
+                continue;
+            };
+            let elapsed = now.saturating_duration_since(playback.started);
+
+            let time = if effect.length.is_zero() {
+                elapsed.checked_sub(effect.delay)
+            } else {
+                // Each repeat waits out the delay again, like the kernel does
+                let cycle = effect.delay + effect.length;
+                let repeat = (elapsed.as_nanos() / cycle.as_nanos()) as u32;
+                if repeat >= playback.count {
+                    effect.playback = None;
+                    continue;
+                }
+                (elapsed - cycle * repeat).checked_sub(effect.delay)
+            };
+            // Waiting out its delay counts, the game asked for that too
+            active = true;
+            let Some(time) = time else {
+                continue;
+            };
+
+            let output = effect.output(time);
+            total.large += output.large;
+            total.small += output.small;
+            total.force += output.force;
+        }
+
+        RumbleState {
+            large_motor: (total.large * self.gain).clamp(0.0, 1.0),
+            small_motor: (total.small * self.gain).clamp(0.0, 1.0),
+            force: (total.force * self.gain).clamp(-1.0, 1.0),
+            updated: self.updated,
+            active,
+        }
+    }
+}
+
+impl Effect {
+    /// Output `time` into the current play
+    fn output(&self, time: Duration) -> EffectOutput {
+        match self.params {
+            EffectParams::Rumble(rumble) => EffectOutput {
+                large: rumble.strong_magnitude as f32 / u16::MAX as f32,
+                small: rumble.weak_magnitude as f32 / u16::MAX as f32,
+                force: 0.0,
+            },
+            EffectParams::Constant(constant) => {
+                let level = self.envelope(&constant.envelope, constant.level, time);
+                EffectOutput { force: level * self.right, ..Default::default() }
+            }
+            EffectParams::Periodic(periodic) => {
+                let magnitude = self.envelope(&periodic.envelope, periodic.magnitude, time);
+                let period = Duration::from_millis(periodic.period.max(1) as u64);
+                // phase is a fraction of the period, 0x10000 being all of it
+                let cycles = time.as_secs_f32() / period.as_secs_f32() + periodic.phase as f32 / 65536.0;
+                let value = magnitude * wave(periodic.waveform, cycles.fract())
+                    + periodic.offset as f32 / i16::MAX as f32;
+                EffectOutput { force: value * self.right, ..Default::default() }
+            }
+        }
+    }
+
+    /// `level` (-0x7FFF to 0x7FFF) shaped by the attack and fade, as -1 to 1
+    fn envelope(&self, envelope: &FFEnvelope, level: i16, time: Duration) -> f32 {
+        let level = level as f32 / i16::MAX as f32;
+        let magnitude = level.abs();
+        let attack_length = Duration::from_millis(envelope.attack_length as u64);
+        let fade_length = Duration::from_millis(envelope.fade_length as u64);
+
+        let shaped = if time < attack_length {
+            let start = envelope.attack_level as f32 / i16::MAX as f32;
+            start + (magnitude - start) * time.as_secs_f32() / attack_length.as_secs_f32()
+        } else if !self.length.is_zero() && !fade_length.is_zero() && time + fade_length > self.length {
+            let end = envelope.fade_level as f32 / i16::MAX as f32;
+            let left = self.length.saturating_sub(time).as_secs_f32() / fade_length.as_secs_f32();
+            end + (magnitude - end) * left
+        } else {
+            magnitude
+        };
+        shaped.copysign(level)
+    }
+}
+
+/// One cycle of `waveform`, `position` 0 to 1, as -1 to 1
+fn wave(waveform: u16, position: f32) -> f32 {
+    match waveform {
+        FF_SQUARE => if position < 0.5 { 1.0 } else { -1.0 },
+        FF_TRIANGLE => 1.0 - 4.0 * (position - 0.5).abs(),
+        FF_SAW_UP => 2.0 * position - 1.0,
+        FF_SAW_DOWN => 1.0 - 2.0 * position,
+        _ => (position * TAU).sin(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A full strength constant effect pushing right
+    fn constant(id: i16, length: u16, delay: u16, envelope: FFEnvelope) -> FFEffect {
+        let mut effect = FFEffect {
+            type_: FF_CONSTANT,
+            id,
+            direction: 0x4000,
+            trigger: [0; 4],
+            replay: [0; 4],
+            _pad: [0; 2],
+            u: [0; 48],
+        };
+        effect.replay[..2].copy_from_slice(&length.to_ne_bytes());
+        effect.replay[2..].copy_from_slice(&delay.to_ne_bytes());
+        let params = FFConstantEffect { level: i16::MAX, envelope };
+        unsafe { std::ptr::write_unaligned(effect.u.as_mut_ptr() as *mut FFConstantEffect, params) };
+        effect
+    }
+
+    fn mixer_playing(effect: &FFEffect, count: i32, now: Instant) -> EffectMixer {
+        let mut mixer = EffectMixer::default();
+        assert!(mixer.upload(effect, now));
+        mixer.play(effect.id, count, now);
+        mixer
+    }
+
+    fn at(mixer: &mut EffectMixer, start: Instant, ms: u64) -> RumbleState {
+        mixer.mix(start + Duration::from_millis(ms))
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 0.01, "expected {expected}, got {actual}");
+    }
+
+    #[test]
+    fn stops_after_count_plays() {
+        let start = Instant::now();
+        let mut mixer = mixer_playing(&constant(0, 100, 0, FFEnvelope::default()), 2, start);
+
+        assert_near(at(&mut mixer, start, 50).force, 1.0);
+        let second = at(&mut mixer, start, 150);
+        assert_near(second.force, 1.0);
+        assert!(second.active);
+
+        let done = at(&mut mixer, start, 250);
+        assert_near(done.force, 0.0);
+        assert!(!done.active);
+    }
+
+    #[test]
+    fn each_repeat_waits_out_the_delay() {
+        let start = Instant::now();
+        let mut mixer = mixer_playing(&constant(0, 100, 50, FFEnvelope::default()), 2, start);
+
+        // Silent but still active while waiting
+        let waiting = at(&mut mixer, start, 20);
+        assert_near(waiting.force, 0.0);
+        assert!(waiting.active);
+        assert_near(at(&mut mixer, start, 100).force, 1.0);
+
+        // The second play starts at 150ms and waits again until 200ms
+        let waiting_again = at(&mut mixer, start, 170);
+        assert_near(waiting_again.force, 0.0);
+        assert!(waiting_again.active);
+        assert_near(at(&mut mixer, start, 250).force, 1.0);
+
+        assert!(!at(&mut mixer, start, 310).active);
+    }
+
+    #[test]
+    fn envelope_attacks_and_fades() {
+        let start = Instant::now();
+        let envelope = FFEnvelope { attack_length: 100, attack_level: 0, fade_length: 100, fade_level: 0 };
+        let mut mixer = mixer_playing(&constant(0, 1000, 0, envelope), 1, start);
+
+        assert_near(at(&mut mixer, start, 0).force, 0.0);
+        assert_near(at(&mut mixer, start, 50).force, 0.5);
+        assert_near(at(&mut mixer, start, 500).force, 1.0);
+        assert_near(at(&mut mixer, start, 950).force, 0.5);
+    }
+
+    #[test]
+    fn zero_length_plays_until_stopped() {
+        let start = Instant::now();
+        let envelope = FFEnvelope { fade_length: 100, ..Default::default() };
+        let mut mixer = mixer_playing(&constant(0, 0, 0, envelope), 1, start);
+
+        // No fade without a length to fade towards
+        let playing = at(&mut mixer, start, 10_000);
+        assert_near(playing.force, 1.0);
+        assert!(playing.active);
+
+        mixer.play(0, 0, start + Duration::from_millis(10_000));
+        assert!(!at(&mut mixer, start, 10_001).active);
+    }
+}
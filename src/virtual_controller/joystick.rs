@@ -0,0 +1,117 @@
+use super::linux::{gamepad_ff, GamepadFf};
+use super::uinput::{AbsAxis, DeviceSpec, UinputDevice};
+use super::uinput_ffi::*;
+use super::{
+    FfPassthrough, JoystickSettings, RumbleState, VirtualController, XboxControllerState, MAX_JOYSTICK_AXES,
+    MAX_JOYSTICK_BUTTONS, MAX_JOYSTICK_HATS, MAX_JOYSTICK_RESOLUTION, MIN_JOYSTICK_RESOLUTION,
+};
+use std::path::Path;
+
+/// In the same order as `JOYSTICK_AXIS_NAMES`
+const AXIS_CODES: [u16; MAX_JOYSTICK_AXES] = [ABS_X, ABS_Y, ABS_Z, ABS_RX, ABS_RY, ABS_RZ, ABS_THROTTLE, ABS_RUDDER];
+
+/// evdev code of joystick button `index`
+fn button_code(index: usize) -> u16 {
+    let index = index as u16;
+    if index < BTN_JOYSTICK_COUNT {
+        BTN_JOYSTICK + index
+    } else {
+        BTN_TRIGGER_HAPPY1 + index - BTN_JOYSTICK_COUNT
+    }
+}
+
+/// A plain joystick with as many axes, buttons and hats as the profile asks for.
+/// Axes are unsigned like DirectInput's, 0 to 2^resolution - 1.
+pub struct VirtualJoystick {
+    // Declared first so the FF thread stops before the device is destroyed
+    ff: GamepadFf,
+    device: UinputDevice,
+    axes: usize,
+    axis_max: i32,
+    buttons: usize,
+    hats: usize,
+    connected: bool,
+}
+
+impl VirtualJoystick {
+    pub fn new(settings: &JoystickSettings, passthrough: &[u16]) -> anyhow::Result<Self> {
+        let axes = settings.axes.clamp(1, MAX_JOYSTICK_AXES);
+        let buttons = settings.buttons.clamp(1, MAX_JOYSTICK_BUTTONS);
+        let hats = settings.hats.min(MAX_JOYSTICK_HATS);
+        let resolution = settings.resolution.clamp(MIN_JOYSTICK_RESOLUTION, MAX_JOYSTICK_RESOLUTION);
+        let axis_max = (1i32 << resolution) - 1;
+
+        let mut abs: Vec<AbsAxis> = AXIS_CODES[..axes].iter().map(|&code| AbsAxis::new(code, 0, axis_max)).collect();
+        for hat in 0..hats as u16 {
+            abs.push(AbsAxis::new(ABS_HAT0X + hat * 2, -1, 1));
+            abs.push(AbsAxis::new(ABS_HAT0Y + hat * 2, -1, 1));
+        }
+        let keys: Vec<u16> = (0..buttons).map(button_code).collect();
+
+        let device = UinputDevice::create(&DeviceSpec {
+            name: "RoWheel Virtual Joystick",
+            vendor: 0x1209, // pid.codes
+            product: 0x0001, // pid.codes test PID
+            version: 0x0100,
+            keys: &keys,
+            rel: &[],
+            axes: &abs,
+            misc: &[],
+            props: &[],
+            ff: &gamepad_ff(passthrough),
+        })?;
+        let ff = GamepadFf::start(device.fd(), passthrough)?;
+
+        log::info!("Uinput joystick created: {} axes at {} bits, {} buttons, {} hats", axes, resolution, buttons, hats);
+
+        Ok(Self {
+            ff,
+            device,
+            axes,
+            axis_max,
+            buttons,
+            hats,
+            connected: true,
+        })
+    }
+}
+
+impl VirtualController for VirtualJoystick {
+    fn update(&mut self, state: &XboxControllerState) -> anyhow::Result<()> {
+        if !self.connected {
+            return Ok(());
+        }
+
+        let joystick = &state.joystick;
+        for (&code, &value) in AXIS_CODES.iter().zip(&joystick.axes).take(self.axes) {
+            let value = ((value.clamp(-1.0, 1.0) + 1.0) / 2.0 * self.axis_max as f32).round() as i32;
+            self.device.write_event(EV_ABS, code, value)?;
+        }
+        for index in 0..self.buttons {
+            self.device.write_event(EV_KEY, button_code(index), joystick.button(index) as i32)?;
+        }
+        for hat in 0..self.hats {
+            let (x, y) = joystick.hat(hat);
+            self.device.write_event(EV_ABS, ABS_HAT0X + hat as u16 * 2, x)?;
+            self.device.write_event(EV_ABS, ABS_HAT0Y + hat as u16 * 2, y)?;
+        }
+
+        self.device.sync()
+    }
+
+    fn get_rumble(&mut self) -> anyhow::Result<RumbleState> {
+        self.ff.rumble()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    fn take_passthrough(&mut self) -> Vec<FfPassthrough> {
+        self.ff.take_passthrough()
+    }
+
+    fn event_node(&self) -> Option<&Path> {
+        self.device.event_node()
+    }
+}
@@ -0,0 +1,104 @@
+use super::uinput::{DeviceSpec, UinputDevice};
+use super::uinput_ffi::*;
+use super::{KeyboardMouseSettings, KeyboardMouseState, MouseButton, VirtualKeyboardMouse, KEYBOARD_KEYS};
+use std::time::Instant;
+
+/// Mouse axes closer to center than this don't move the pointer, so a wheel resting
+/// slightly off center doesn't drift the camera
+const MOUSE_DEADZONE: f32 = 0.02;
+
+/// By `MouseButton`
+const MOUSE_BUTTONS: [u16; 3] = [BTN_LEFT, BTN_RIGHT, BTN_MIDDLE];
+
+/// A keyboard and mouse in one uinput device, for games that want keys next to the gamepad
+pub struct UinputKeyboardMouse {
+    device: UinputDevice,
+    /// What was last sent, so only changes are written
+    keys: u128,
+    mouse_buttons: [bool; 3],
+    last_update: Option<Instant>,
+    /// Fractions of a pixel carried over to the next update
+    remainder: (f32, f32),
+}
+
+impl UinputKeyboardMouse {
+    pub fn new() -> anyhow::Result<Self> {
+        let mut keys: Vec<u16> = KEYBOARD_KEYS.iter().map(|(code, _)| *code).collect();
+        keys.extend(MOUSE_BUTTONS);
+
+        let device = UinputDevice::create(&DeviceSpec {
+            name: "RoWheel Virtual Keyboard and Mouse",
+            vendor: 0x1209, // pid.codes
+            product: 0x0002, // pid.codes test PID
+            version: 0x0100,
+            keys: &keys,
+            rel: &[REL_X, REL_Y],
+            axes: &[],
+            misc: &[],
+            props: &[],
+            ff: &[],
+        })?;
+
+        Ok(Self {
+            device,
+            keys: 0,
+            mouse_buttons: [false; 3],
+            last_update: None,
+            remainder: (0.0, 0.0),
+        })
+    }
+}
+
+/// Whole pixels to move this update, keeping the fraction for the next
+fn mouse_step(value: f32, speed: f32, seconds: f32, remainder: &mut f32) -> i32 {
+    if value.abs() < MOUSE_DEADZONE {
+        *remainder = 0.0;
+        return 0;
+    }
+    let distance = value.clamp(-1.0, 1.0) * speed * seconds + *remainder;
+    let pixels = distance.trunc();
+    *remainder = distance - pixels;
+    pixels as i32
+}
+
+impl VirtualKeyboardMouse for UinputKeyboardMouse {
+    fn update(&mut self, state: &KeyboardMouseState, settings: &KeyboardMouseSettings, now: Instant) -> anyhow::Result<()> {
+        let mut changed = false;
+
+        let keys_changed = state.keys ^ self.keys;
+        for &(code, _) in KEYBOARD_KEYS {
+            if keys_changed & (1 << code) != 0 {
+                self.device.write_event(EV_KEY, code, state.key(code) as i32)?;
+                changed = true;
+            }
+        }
+        self.keys = state.keys;
+
+        for button in MouseButton::ALL {
+            let pressed = state.mouse_buttons[button as usize];
+            if pressed != self.mouse_buttons[button as usize] {
+                self.device.write_event(EV_KEY, MOUSE_BUTTONS[button as usize], pressed as i32)?;
+                changed = true;
+            }
+        }
+        self.mouse_buttons = state.mouse_buttons;
+
+        let seconds = self.last_update.map(|last| now.saturating_duration_since(last).as_secs_f32()).unwrap_or(0.0);
+        self.last_update = Some(now);
+        let dx = mouse_step(state.mouse_x, settings.mouse_speed, seconds, &mut self.remainder.0);
+        let dy = mouse_step(state.mouse_y, settings.mouse_speed, seconds, &mut self.remainder.1);
+        if dx != 0 {
+            self.device.write_event(EV_REL, REL_X, dx)?;
+            changed = true;
+        }
+        if dy != 0 {
+            self.device.write_event(EV_REL, REL_Y, dy)?;
+            changed = true;
+        }
+
+        if changed {
+            self.device.sync()?;
+        }
+        Ok(())
+    }
+}
@@ -1,170 +1,84 @@
+use super::uinput::{AbsAxis, DeviceSpec, UinputDevice};
 use super::uinput_ffi::*;
-use super::{RumbleState, VirtualController, XboxControllerState};
-use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
-use std::io::Write;
-use std::os::unix::io::AsRawFd;
+use super::ff_mixer::EffectMixer;
+use super::{FfPassthrough, PassthroughEffect, RumbleState, VirtualController, XboxControllerState};
+use std::collections::HashSet;
+use std::os::unix::io::RawFd;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
+use std::time::Instant;
+
+/// FF effect types every virtual gamepad advertises, periodic needs each waveform too
+const GAMEPAD_FF: &[u16] = &[FF_RUMBLE, FF_CONSTANT, FF_PERIODIC, FF_SQUARE,
+                             FF_TRIANGLE, FF_SINE, FF_SAW_UP, FF_SAW_DOWN, FF_GAIN];
+/// The mixer can't play these, so they're only advertised when the wheel plays them
+const CONDITION_FF: &[u16] = &[FF_SPRING, FF_DAMPER];
+
+/// FF bits for a gamepad whose game effects of the `passthrough` types go to the wheel
+pub(super) fn gamepad_ff(passthrough: &[u16]) -> Vec<u16> {
+    let mut ff = GAMEPAD_FF.to_vec();
+    ff.extend(CONDITION_FF.iter().filter(|kind| passthrough.contains(kind)));
+    ff
+}
 
-pub struct VirtualXboxController {
-    uinput_file: Option<File>,
-    connected: bool,
-    rumble_state: Arc<Mutex<RumbleState>>,
-    ff_effects: Arc<Mutex<HashMap<i16, FFRumbleEffect>>>,
+const XBOX_KEYS: &[u16] = &[BTN_A, BTN_B, BTN_X, BTN_Y, BTN_TL, BTN_TR,
+                            BTN_SELECT, BTN_START, BTN_MODE, BTN_THUMBL, BTN_THUMBR];
+
+const XBOX_AXES: &[AbsAxis] = &[
+    AbsAxis::new(ABS_X, AXIS_MIN, AXIS_MAX),
+    AbsAxis::new(ABS_Y, AXIS_MIN, AXIS_MAX),
+    AbsAxis::new(ABS_RX, AXIS_MIN, AXIS_MAX),
+    AbsAxis::new(ABS_RY, AXIS_MIN, AXIS_MAX),
+    AbsAxis::new(ABS_Z, TRIGGER_MIN, TRIGGER_MAX),
+    AbsAxis::new(ABS_RZ, TRIGGER_MIN, TRIGGER_MAX),
+    AbsAxis::new(ABS_HAT0X, -1, 1),
+    AbsAxis::new(ABS_HAT0Y, -1, 1),
+];
+
+/// Answers the FF uploads, erases and plays a game sends a virtual gamepad, on its own thread
+pub(super) struct GamepadFf {
+    /// Effects the game uploaded, shared with the FF thread
+    ff_mixer: Arc<Mutex<EffectMixer>>,
+    passthrough: Arc<Mutex<Vec<FfPassthrough>>>,
     ff_thread_handle: Option<JoinHandle<()>>,
     ff_thread_running: Arc<std::sync::atomic::AtomicBool>,
 }
 
-impl VirtualXboxController {
-    pub fn new() -> anyhow::Result<Self> {
-        let mut controller = Self {
-            uinput_file: None,
-            connected: false,
-            rumble_state: Arc::new(Mutex::new(RumbleState::default())),
-            ff_effects: Arc::new(Mutex::new(HashMap::new())),
+impl GamepadFf {
+    /// Start answering FF requests on the uinput device behind `fd`. Effects of the
+    /// `passthrough` types (and periodic waveforms) go to `take_passthrough` instead of the mixer.
+    pub fn start(fd: RawFd, passthrough: &[u16]) -> anyhow::Result<Self> {
+        let mut ff = Self {
+            ff_mixer: Arc::new(Mutex::new(EffectMixer::default())),
+            passthrough: Arc::new(Mutex::new(Vec::new())),
             ff_thread_handle: None,
             ff_thread_running: Arc::new(std::sync::atomic::AtomicBool::new(false)),
         };
 
-        controller.connect()?;
-        Ok(controller)
-    }
-
-    fn connect(&mut self) -> anyhow::Result<()> {
-        let uinput_file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open(UINPUT_PATH)
-            .map_err(|e| anyhow::anyhow!(
-                "Failed to open {}: {}. Try: sudo chmod 666 /dev/uinput",
-                UINPUT_PATH, e
-            ))?;
-
-        let uinput_fd = uinput_file.as_raw_fd();
-
-        // Make sure uinput can't block when we try to poll things
-        unsafe {
-            let flags = libc::fcntl(uinput_fd, libc::F_GETFL);
-            if flags < 0 {
-                return Err(anyhow::anyhow!("Failed to get uinput file flags"));
-            }
-            if libc::fcntl(uinput_fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
-                return Err(anyhow::anyhow!("Failed to set uinput non-blocking mode"));
-            }
-        }
-
-        unsafe {
-            // Enable event types
-            if libc::ioctl(uinput_fd, UI_SET_EVBIT, EV_KEY as libc::c_int) < 0 {
-                return Err(anyhow::anyhow!("Failed to set EV_KEY"));
-            }
-            if libc::ioctl(uinput_fd, UI_SET_EVBIT, EV_ABS as libc::c_int) < 0 {
-                return Err(anyhow::anyhow!("Failed to set EV_ABS"));
-            }
-            if libc::ioctl(uinput_fd, UI_SET_EVBIT, EV_SYN as libc::c_int) < 0 {
-                return Err(anyhow::anyhow!("Failed to set EV_SYN"));
-            }
-            // Enable force feedback support
-            if libc::ioctl(uinput_fd, UI_SET_EVBIT, EV_FF as libc::c_int) < 0 {
-                return Err(anyhow::anyhow!("Failed to set EV_FF"));
-            }
-
-            // Set buttons
-            for btn in [BTN_A, BTN_B, BTN_X, BTN_Y, BTN_TL, BTN_TR,
-                        BTN_SELECT, BTN_START, BTN_MODE, BTN_THUMBL, BTN_THUMBR] {
-                if libc::ioctl(uinput_fd, UI_SET_KEYBIT, btn as libc::c_int) < 0 {
-                    return Err(anyhow::anyhow!("Failed to set button {}", btn));
-                }
-            }
-
-            // Set absolute axes
-            for axis in [ABS_X, ABS_Y, ABS_RX, ABS_RY, ABS_Z, ABS_RZ, ABS_HAT0X, ABS_HAT0Y] {
-                if libc::ioctl(uinput_fd, UI_SET_ABSBIT, axis as libc::c_int) < 0 {
-                    return Err(anyhow::anyhow!("Failed to set axis {}", axis));
-                }
-            }
-
-            // Set force feedback effect type
-            if libc::ioctl(uinput_fd, UI_SET_FFBIT, FF_RUMBLE as libc::c_int) < 0 {
-                return Err(anyhow::anyhow!("Failed to set FF_RUMBLE"));
-            }
-
-            // Create device struct
-            let mut dev: UinputUserDev = std::mem::zeroed();
-            let name = b"RoWheel Virtual Xbox Controller";
-            dev.name[..name.len()].copy_from_slice(name);
-            dev.id.bustype = 0x03; // BUS_USB
-            dev.id.vendor = 0x045e; // Microsoft
-            dev.id.product = 0x028e; // Xbox 360 Controller
-            dev.id.version = 0x0110;
-            dev.ff_effects_max = 16; // Support up to 16 force feedback effects
-
-            // Set axis ranges
-            dev.absmin[ABS_X as usize] = AXIS_MIN;
-            dev.absmax[ABS_X as usize] = AXIS_MAX;
-            dev.absmin[ABS_Y as usize] = AXIS_MIN;
-            dev.absmax[ABS_Y as usize] = AXIS_MAX;
-            dev.absmin[ABS_RX as usize] = AXIS_MIN;
-            dev.absmax[ABS_RX as usize] = AXIS_MAX;
-            dev.absmin[ABS_RY as usize] = AXIS_MIN;
-            dev.absmax[ABS_RY as usize] = AXIS_MAX;
-            dev.absmin[ABS_Z as usize] = TRIGGER_MIN;
-            dev.absmax[ABS_Z as usize] = TRIGGER_MAX;
-            dev.absmin[ABS_RZ as usize] = TRIGGER_MIN;
-            dev.absmax[ABS_RZ as usize] = TRIGGER_MAX;
-            dev.absmin[ABS_HAT0X as usize] = -1;
-            dev.absmax[ABS_HAT0X as usize] = 1;
-            dev.absmin[ABS_HAT0Y as usize] = -1;
-            dev.absmax[ABS_HAT0Y as usize] = 1;
-
-            // Write device struct
-            let dev_bytes = std::slice::from_raw_parts(
-                &dev as *const _ as *const u8,
-                std::mem::size_of::<UinputUserDev>()
-            );
-
-            if libc::write(uinput_fd, dev_bytes.as_ptr() as *const libc::c_void, dev_bytes.len()) < 0 {
-                return Err(anyhow::anyhow!("Failed to write device struct"));
-            }
-
-            // Create device
-            if libc::ioctl(uinput_fd, UI_DEV_CREATE) < 0 {
-                return Err(anyhow::anyhow!("Failed to create device: {}", std::io::Error::last_os_error()));
-            }
-        }
-
-        self.uinput_file = Some(uinput_file);
-        self.connected = true;
-
-        log::info!("Uinput gamepad created");
-
-        self.start_ff_polling_thread()?;
-
-        Ok(())
-    }
-
-    fn start_ff_polling_thread(&mut self) -> anyhow::Result<()> {
-        let fd = match self.uinput_file.as_ref() {
-            Some(f) => f.as_raw_fd(),
-            None => return Err(anyhow::anyhow!("No uinput file available")),
-        };
-
         // Duplicate the file descriptor so the thread has its own reference
         let thread_fd = unsafe { libc::dup(fd) };
         if thread_fd < 0 {
             return Err(anyhow::anyhow!("Failed to duplicate uinput fd: {}", std::io::Error::last_os_error()));
         }
 
-        let rumble_state = Arc::clone(&self.rumble_state);
-        let ff_effects = Arc::clone(&self.ff_effects);
-        let running = Arc::clone(&self.ff_thread_running);
+        let ff_mixer = Arc::clone(&ff.ff_mixer);
+        let passthrough_types: HashSet<u16> = passthrough.iter().copied().collect();
+        let passthrough = Arc::clone(&ff.passthrough);
+        let running = Arc::clone(&ff.ff_thread_running);
 
         running.store(true, std::sync::atomic::Ordering::SeqCst);
 
         let handle = std::thread::spawn(move || {
             log::info!("FF polling thread started with fd={}", thread_fd);
             let mut buffer = [0u8; std::mem::size_of::<InputEvent>()];
+            // Game effect ids that went to passthrough, so their play and erase events follow
+            let mut passthrough_ids: HashSet<i16> = HashSet::new();
+            let queue = |event: FfPassthrough| {
+                if let Ok(mut queue) = passthrough.lock() {
+                    queue.push(event);
+                }
+            };
 
             while running.load(std::sync::atomic::Ordering::SeqCst) {
                 // Use poll to wait for events with timeout
@@ -235,25 +149,37 @@ impl VirtualXboxController {
                                 // Accept the effect
                                 upload.retval = 0;
 
-                                // Extract rumble magnitudes and store
-                                if upload.effect.type_ == FF_RUMBLE {
-                                    // Debug: dump first 8 bytes of union to verify alignment
-                                    log::debug!("FF effect union raw bytes: {:02x} {:02x} {:02x} {:02x} {:02x} {:02x} {:02x} {:02x}",
-                                               upload.effect.u[0], upload.effect.u[1],
-                                               upload.effect.u[2], upload.effect.u[3],
-                                               upload.effect.u[4], upload.effect.u[5],
-                                               upload.effect.u[6], upload.effect.u[7]);
-
-                                    let rumble_effect: FFRumbleEffect = unsafe {
-                                        std::ptr::read_unaligned(upload.effect.u.as_ptr() as *const FFRumbleEffect)
-                                    };
-                                    if let Ok(mut effects) = ff_effects.lock() {
-                                        effects.insert(upload.effect.id, rumble_effect);
-                                        log::info!("Stored FF effect ID {} with strong_magnitude={}, weak_magnitude={}",
-                                                   upload.effect.id, rumble_effect.strong_magnitude, rumble_effect.weak_magnitude);
-                                    }
+                                let effect = &upload.effect;
+                                // Only what the wheel can play, anything else would be accepted and never felt
+                                let pass = passthrough_types.contains(&effect.type_)
+                                    && (effect.type_ != FF_PERIODIC || passthrough_types.contains(&effect.waveform()));
+                                let accepted = if pass {
+                                    passthrough_ids.insert(effect.id);
+                                    let mut params = [0u8; 32];
+                                    params.copy_from_slice(&effect.u[..32]);
+                                    queue(FfPassthrough::Upload {
+                                        id: effect.id,
+                                        effect: PassthroughEffect {
+                                            kind: effect.type_,
+                                            direction: effect.direction,
+                                            trigger: effect.trigger,
+                                            replay: effect.replay,
+                                            params,
+                                        },
+                                    });
+                                    true
                                 } else {
-                                    log::warn!("Uploaded effect is not FF_RUMBLE type: {}", upload.effect.type_);
+                                    // Was passed through before, but now it goes to the mixer
+                                    if passthrough_ids.remove(&effect.id) {
+                                        queue(FfPassthrough::Erase(effect.id));
+                                    }
+                                    ff_mixer.lock()
+                                        .map(|mut mixer| mixer.upload(effect, Instant::now()))
+                                        .unwrap_or(false)
+                                };
+                                if !accepted {
+                                    log::warn!("Rejecting FF effect of unsupported type {}", upload.effect.type_);
+                                    upload.retval = -libc::EINVAL;
                                 }
 
                                 if unsafe { libc::ioctl(thread_fd, UI_END_FF_UPLOAD, &upload as *const _) } < 0 {
@@ -272,11 +198,13 @@ impl VirtualXboxController {
                                     continue;
                                 }
 
-                                if let Ok(mut effects) = ff_effects.lock() {
-                                    if effects.remove(&(erase.effect_id as i16)).is_some() {
-                                        log::debug!("Removed FF effect ID {} from cache", erase.effect_id);
-                                    }
+                                let id = erase.effect_id as i16;
+                                if passthrough_ids.remove(&id) {
+                                    queue(FfPassthrough::Erase(id));
+                                } else if let Ok(mut mixer) = ff_mixer.lock() {
+                                    mixer.erase(id, Instant::now());
                                 }
+                                log::debug!("Erased FF effect ID {}", erase.effect_id);
 
                                 erase.retval = 0;
 
@@ -288,29 +216,20 @@ impl VirtualXboxController {
                         }
                     }
                     else if event.type_ == EV_FF {
-                        log::debug!("FF play event: effect_id={}, count={}", event.code, event.value);
-
-                        if event.value > 0 {
-                            // Play effect (count > 0)
-                            if let Ok(effects) = ff_effects.lock() {
-                                if let Some(rumble_effect) = effects.get(&(event.code as i16)) {
-                                    if let Ok(mut state) = rumble_state.lock() {
-                                        state.large_motor = rumble_effect.strong_magnitude as f32 / u16::MAX as f32;
-                                        state.small_motor = rumble_effect.weak_magnitude as f32 / u16::MAX as f32;
-                                        log::info!("FF effect {} playing - setting rumble: large={:.2}, small={:.2}",
-                                                   event.code, state.large_motor, state.small_motor);
-                                    }
-                                } else {
-                                    log::warn!("EV_FF play event for unknown effect ID {}", event.code);
-                                }
+                        log::debug!("FF event: code={}, value={}", event.code, event.value);
+
+                        let id = event.code as i16;
+                        if event.code == FF_GAIN {
+                            if let Ok(mut mixer) = ff_mixer.lock() {
+                                mixer.set_gain(event.value, Instant::now());
                             }
-                        } else {
-                            // Stop effect (count == 0)
-                            if let Ok(mut state) = rumble_state.lock() {
-                                state.large_motor = 0.0;
-                                state.small_motor = 0.0;
-                                log::info!("FF effect {} stopped", event.code);
+                            if !passthrough_types.is_empty() {
+                                queue(FfPassthrough::Gain(event.value.clamp(0, u16::MAX as i32) as u16));
                             }
+                        } else if passthrough_ids.contains(&id) {
+                            queue(FfPassthrough::Play { id, count: event.value });
+                        } else if let Ok(mut mixer) = ff_mixer.lock() {
+                            mixer.play(id, event.value, Instant::now());
                         }
                     }
                 }
@@ -323,34 +242,25 @@ impl VirtualXboxController {
             log::info!("FF polling stopped");
         });
 
-        self.ff_thread_handle = Some(handle);
-        Ok(())
+        ff.ff_thread_handle = Some(handle);
+        Ok(ff)
     }
 
-    fn write_event(&mut self, type_: u16, code: u16, value: i32) -> anyhow::Result<()> {
-        if let Some(ref mut file) = self.uinput_file { // Use uinput_file
-            let event = InputEvent::new(type_, code, value);
-            let bytes = unsafe {
-                std::slice::from_raw_parts(
-                    &event as *const _ as *const u8,
-                    std::mem::size_of::<InputEvent>()
-                )
-            };
-            file.write_all(bytes)?;
-        }
-        Ok(())
+    pub fn rumble(&self) -> anyhow::Result<RumbleState> {
+        let mut mixer = self.ff_mixer.lock()
+            .map_err(|_| anyhow::anyhow!("Failed to lock FF effects"))?;
+        let mut rumble = mixer.mix(Instant::now());
+        // Effects left behind by a dead thread can't be stopped or erased, let the watchdog fade them
+        rumble.active &= self.ff_thread_handle.as_ref().is_some_and(|handle| !handle.is_finished());
+        Ok(rumble)
     }
 
-    fn sync(&mut self) -> anyhow::Result<()> {
-        self.write_event(EV_SYN, SYN_REPORT, 0)?;
-        if let Some(ref mut file) = self.uinput_file { // Use uinput_file
-            file.flush()?;
-        }
-        Ok(())
+    pub fn take_passthrough(&self) -> Vec<FfPassthrough> {
+        self.passthrough.lock().map(|mut queue| std::mem::take(&mut *queue)).unwrap_or_default()
     }
 }
 
-impl Drop for VirtualXboxController {
+impl Drop for GamepadFf {
     fn drop(&mut self) {
         self.ff_thread_running.store(false, std::sync::atomic::Ordering::SeqCst);
 
@@ -360,12 +270,47 @@ impl Drop for VirtualXboxController {
                 log::error!("FF polling thread panicked: {:?}", e);
             }
         }
+    }
+}
 
-        if let Some(ref file) = self.uinput_file {
-            unsafe {
-                let _ = libc::ioctl(file.as_raw_fd(), UI_DEV_DESTROY);
-            }
-        }
+pub struct VirtualXboxController {
+    // Declared first so the FF thread stops before the device is destroyed
+    ff: GamepadFf,
+    device: UinputDevice,
+    connected: bool,
+}
+
+impl VirtualXboxController {
+    pub fn new(passthrough: &[u16]) -> anyhow::Result<Self> {
+        let device = UinputDevice::create(&DeviceSpec {
+            name: "RoWheel Virtual Xbox Controller",
+            vendor: 0x045e, // Microsoft
+            product: 0x028e, // Xbox 360 Controller
+            version: 0x0110,
+            keys: XBOX_KEYS,
+            rel: &[],
+            axes: XBOX_AXES,
+            misc: &[],
+            props: &[],
+            ff: &gamepad_ff(passthrough),
+        })?;
+        let ff = GamepadFf::start(device.fd(), passthrough)?;
+
+        log::info!("Uinput gamepad created");
+
+        Ok(Self {
+            ff,
+            device,
+            connected: true,
+        })
+    }
+
+    fn write_event(&mut self, type_: u16, code: u16, value: i32) -> anyhow::Result<()> {
+        self.device.write_event(type_, code, value)
+    }
+
+    fn sync(&mut self) -> anyhow::Result<()> {
+        self.device.sync()
     }
 }
 
@@ -421,12 +366,18 @@ impl VirtualController for VirtualXboxController {
     }
 
     fn get_rumble(&mut self) -> anyhow::Result<RumbleState> {
-        let state = self.rumble_state.lock()
-            .map_err(|_| anyhow::anyhow!("Failed to lock rumble state"))?;
-        Ok(state.clone())
+        self.ff.rumble()
     }
 
     fn is_connected(&self) -> bool {
         self.connected
     }
+
+    fn take_passthrough(&mut self) -> Vec<FfPassthrough> {
+        self.ff.take_passthrough()
+    }
+
+    fn event_node(&self) -> Option<&Path> {
+        self.device.event_node()
+    }
 }
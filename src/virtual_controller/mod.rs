@@ -1,15 +1,284 @@
 #[cfg(target_os = "linux")]
 mod uinput_ffi;
 #[cfg(target_os = "linux")]
+mod uinput;
+#[cfg(target_os = "linux")]
+mod ff_mixer;
+#[cfg(target_os = "linux")]
 mod linux;
+#[cfg(target_os = "linux")]
+mod ds4;
+#[cfg(target_os = "linux")]
+mod joystick;
+#[cfg(target_os = "linux")]
+mod keyboard;
 #[cfg(windows)]
 mod windows;
 
 #[cfg(target_os = "linux")]
 pub use linux::VirtualXboxController;
+#[cfg(target_os = "linux")]
+pub use ds4::VirtualDualShock4;
+#[cfg(target_os = "linux")]
+pub use joystick::VirtualJoystick;
+#[cfg(target_os = "linux")]
+pub use keyboard::UinputKeyboardMouse;
 #[cfg(windows)]
 pub use windows::VirtualXboxController;
 
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Instant;
+
+pub const MAX_JOYSTICK_AXES: usize = 8;
+pub const MAX_JOYSTICK_BUTTONS: usize = 56;
+pub const MAX_JOYSTICK_HATS: usize = 4;
+pub const MIN_JOYSTICK_RESOLUTION: u32 = 8;
+pub const MAX_JOYSTICK_RESOLUTION: u32 = 24;
+/// evdev names of the joystick's axes, in the order they're added
+pub const JOYSTICK_AXIS_NAMES: [&str; MAX_JOYSTICK_AXES] = ["X", "Y", "Z", "RX", "RY", "RZ", "Throttle", "Rudder"];
+
+/// Keys bindings can press, by Linux input event code. Every code is under 128 so
+/// the held keys fit in `KeyboardMouseState::keys`.
+pub const KEYBOARD_KEYS: &[(u16, &str)] = &[
+    (30, "A"), (48, "B"), (46, "C"), (32, "D"), (18, "E"), (33, "F"), (34, "G"), (35, "H"), (23, "I"),
+    (36, "J"), (37, "K"), (38, "L"), (50, "M"), (49, "N"), (24, "O"), (25, "P"), (16, "Q"), (19, "R"),
+    (31, "S"), (20, "T"), (22, "U"), (47, "V"), (17, "W"), (45, "X"), (21, "Y"), (44, "Z"),
+    (11, "0"), (2, "1"), (3, "2"), (4, "3"), (5, "4"), (6, "5"), (7, "6"), (8, "7"), (9, "8"), (10, "9"),
+    (57, "Space"), (28, "Enter"), (1, "Escape"), (15, "Tab"), (14, "Backspace"),
+    (42, "Left Shift"), (29, "Left Ctrl"), (56, "Left Alt"),
+    (103, "Up"), (108, "Down"), (105, "Left"), (106, "Right"),
+    (59, "F1"), (60, "F2"), (61, "F3"), (62, "F4"), (63, "F5"), (64, "F6"),
+    (65, "F7"), (66, "F8"), (67, "F9"), (68, "F10"), (87, "F11"), (88, "F12"),
+];
+
+pub fn key_name(code: u16) -> &'static str {
+    KEYBOARD_KEYS.iter().find(|(c, _)| *c == code).map(|(_, name)| *name).unwrap_or("?")
+}
+
+/// Which gamepad the game sees
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControllerKind {
+    #[default]
+    Xbox360,
+    /// Linux only, Windows falls back to the Xbox 360 pad
+    DualShock4,
+    /// Generic joystick, Linux only like the DualShock 4
+    Joystick,
+}
+
+impl ControllerKind {
+    pub const ALL: [ControllerKind; 3] = [ControllerKind::Xbox360, ControllerKind::DualShock4, ControllerKind::Joystick];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ControllerKind::Xbox360 => "Xbox 360",
+            ControllerKind::DualShock4 => "DualShock 4",
+            ControllerKind::Joystick => "Joystick",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ControllerKind::Xbox360 => "Works with nearly everything",
+            ControllerKind::DualShock4 => "PlayStation layout with touchpad click and motion sensors, for games and Steam Input setups that prefer it",
+            ControllerKind::Joystick => "High resolution axes, separate pedals, lots of buttons and hats, for sims under Wine that take DirectInput wheels",
+        }
+    }
+}
+
+/// Layout of the generic joystick
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JoystickSettings {
+    pub axes: usize,
+    /// Bits per axis
+    pub resolution: u32,
+    pub buttons: usize,
+    pub hats: usize,
+}
+
+impl Default for JoystickSettings {
+    fn default() -> Self {
+        Self {
+            axes: MAX_JOYSTICK_AXES,
+            resolution: 16,
+            buttons: 32,
+            hats: 1,
+        }
+    }
+}
+
+/// Create the virtual gamepad for `kind`. `passthrough` is the effect types (and periodic
+/// waveforms) the wheel plays for the game as they are, empty mixes everything into rumble.
+/// It's fixed for the gamepad's life since it decides which effects the gamepad advertises.
+pub fn create_controller(
+    kind: ControllerKind,
+    joystick: &JoystickSettings,
+    passthrough: &[u16],
+) -> anyhow::Result<Box<dyn VirtualController>> {
+    #[cfg(target_os = "linux")]
+    match kind {
+        ControllerKind::Xbox360 => Ok(Box::new(VirtualXboxController::new(passthrough)?)),
+        ControllerKind::DualShock4 => Ok(Box::new(VirtualDualShock4::new(passthrough)?)),
+        ControllerKind::Joystick => Ok(Box::new(VirtualJoystick::new(joystick, passthrough)?)),
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (joystick, passthrough);
+        if kind != ControllerKind::Xbox360 {
+            log::warn!("{} output isn't supported here, using an Xbox 360 pad", kind.name());
+        }
+        Ok(Box::new(VirtualXboxController::new()?))
+    }
+}
+
+/// How wheel axes move the virtual mouse
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyboardMouseSettings {
+    /// Pixels per second at full deflection
+    pub mouse_speed: f32,
+}
+
+impl Default for KeyboardMouseSettings {
+    fn default() -> Self {
+        Self { mouse_speed: 1000.0 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+impl MouseButton {
+    pub const ALL: [MouseButton; 3] = [Self::Left, Self::Right, Self::Middle];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Left => "Mouse Left",
+            Self::Right => "Mouse Right",
+            Self::Middle => "Mouse Middle",
+        }
+    }
+}
+
+/// Keys and mouse the bindings drive, sent through their own device next to the gamepad
+#[derive(Debug, Clone, Default)]
+pub struct KeyboardMouseState {
+    /// Bit n is the key with event code n
+    pub keys: u128,
+    /// Mouse speed, -1 to 1 with right and down positive
+    pub mouse_x: f32,
+    pub mouse_y: f32,
+    /// By `MouseButton`
+    pub mouse_buttons: [bool; 3],
+}
+
+impl KeyboardMouseState {
+    pub fn key(&self, code: u16) -> bool {
+        code < 128 && self.keys & (1 << code) != 0
+    }
+
+    pub fn press_key(&mut self, code: u16) {
+        if code < 128 {
+            self.keys |= 1 << code;
+        }
+    }
+
+    /// Names of the held keys and mouse buttons
+    pub fn held(&self) -> Vec<&'static str> {
+        let keys = KEYBOARD_KEYS.iter().filter(|(code, _)| self.key(*code)).map(|(_, name)| *name);
+        let buttons = MouseButton::ALL.into_iter().filter(|b| self.mouse_buttons[*b as usize]).map(|b| b.name());
+        keys.chain(buttons).collect()
+    }
+}
+
+pub trait VirtualKeyboardMouse: Send {
+    fn update(&mut self, state: &KeyboardMouseState, settings: &KeyboardMouseSettings, now: Instant) -> anyhow::Result<()>;
+}
+
+/// Create the keyboard and mouse that key and mouse bindings go to
+pub fn create_keyboard_mouse() -> anyhow::Result<Box<dyn VirtualKeyboardMouse>> {
+    #[cfg(target_os = "linux")]
+    return Ok(Box::new(UinputKeyboardMouse::new()?));
+
+    #[cfg(not(target_os = "linux"))]
+    Err(anyhow::anyhow!("Keyboard and mouse output is only supported on Linux"))
+}
+
+/// One direction of a joystick hat
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HatDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl HatDirection {
+    pub const ALL: [HatDirection; 4] = [Self::Up, Self::Down, Self::Left, Self::Right];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Up => "Up",
+            Self::Down => "Down",
+            Self::Left => "Left",
+            Self::Right => "Right",
+        }
+    }
+}
+
+/// Outputs only the generic joystick has
+#[derive(Debug, Clone, Default)]
+pub struct JoystickState {
+    /// -1 to 1, pedals use the whole range and rest at -1
+    pub axes: [f32; MAX_JOYSTICK_AXES],
+    /// Bit n is button n
+    pub buttons: u64,
+    /// Directions held on each hat, by `HatDirection`
+    pub hats: [[bool; 4]; MAX_JOYSTICK_HATS],
+}
+
+impl JoystickState {
+    pub fn button(&self, index: usize) -> bool {
+        index < MAX_JOYSTICK_BUTTONS && self.buttons & (1 << index) != 0
+    }
+
+    pub fn press_button(&mut self, index: usize) {
+        if index < MAX_JOYSTICK_BUTTONS {
+            self.buttons |= 1 << index;
+        }
+    }
+
+    pub fn press_hat(&mut self, hat: usize, direction: HatDirection) {
+        if let Some(hat) = self.hats.get_mut(hat) {
+            hat[direction as usize] = true;
+        }
+    }
+
+    /// (x, y) of a hat, -1 to 1 each with up and left negative
+    pub fn hat(&self, hat: usize) -> (i32, i32) {
+        let Some(held) = self.hats.get(hat) else {
+            return (0, 0);
+        };
+        let axis = |negative: HatDirection, positive: HatDirection| {
+            match (held[negative as usize], held[positive as usize]) {
+                (true, false) => -1,
+                (false, true) => 1,
+                _ => 0,
+            }
+        };
+        (axis(HatDirection::Left, HatDirection::Right), axis(HatDirection::Up, HatDirection::Down))
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct XboxControllerState {
     pub left_stick_x: f32,
@@ -19,6 +288,8 @@ pub struct XboxControllerState {
     pub left_trigger: f32,
     pub right_trigger: f32,
     pub buttons: XboxButtons,
+    pub joystick: JoystickState,
+    pub keyboard_mouse: KeyboardMouseState,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -38,15 +309,160 @@ pub struct XboxButtons {
     pub dpad_down: bool,
     pub dpad_left: bool,
     pub dpad_right: bool,
+    /// DualShock 4 touchpad click, the Xbox pad has nothing to put it on
+    pub touchpad: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum XboxButton {
+    A,
+    B,
+    X,
+    Y,
+    LeftBumper,
+    RightBumper,
+    Back,
+    Start,
+    Guide,
+    LeftThumb,
+    RightThumb,
+    DpadUp,
+    DpadDown,
+    DpadLeft,
+    DpadRight,
+    Touchpad,
+}
+
+impl XboxButton {
+    pub const ALL: [XboxButton; 16] = [
+        Self::A, Self::B, Self::X, Self::Y,
+        Self::LeftBumper, Self::RightBumper,
+        Self::Back, Self::Start, Self::Guide,
+        Self::LeftThumb, Self::RightThumb,
+        Self::DpadUp, Self::DpadDown, Self::DpadLeft, Self::DpadRight,
+        Self::Touchpad,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::A => "A",
+            Self::B => "B",
+            Self::X => "X",
+            Self::Y => "Y",
+            Self::LeftBumper => "Left Bumper",
+            Self::RightBumper => "Right Bumper",
+            Self::Back => "Back",
+            Self::Start => "Start",
+            Self::Guide => "Guide",
+            Self::LeftThumb => "Left Thumb",
+            Self::RightThumb => "Right Thumb",
+            Self::DpadUp => "DPad Up",
+            Self::DpadDown => "DPad Down",
+            Self::DpadLeft => "DPad Left",
+            Self::DpadRight => "DPad Right",
+            Self::Touchpad => "Touchpad (DS4)",
+        }
+    }
+}
+
+impl XboxButtons {
+    pub fn get(&self, button: XboxButton) -> bool {
+        match button {
+            XboxButton::A => self.a,
+            XboxButton::B => self.b,
+            XboxButton::X => self.x,
+            XboxButton::Y => self.y,
+            XboxButton::LeftBumper => self.left_bumper,
+            XboxButton::RightBumper => self.right_bumper,
+            XboxButton::Back => self.back,
+            XboxButton::Start => self.start,
+            XboxButton::Guide => self.guide,
+            XboxButton::LeftThumb => self.left_thumb,
+            XboxButton::RightThumb => self.right_thumb,
+            XboxButton::DpadUp => self.dpad_up,
+            XboxButton::DpadDown => self.dpad_down,
+            XboxButton::DpadLeft => self.dpad_left,
+            XboxButton::DpadRight => self.dpad_right,
+            XboxButton::Touchpad => self.touchpad,
+        }
+    }
+
+    pub fn set(&mut self, button: XboxButton, pressed: bool) {
+        let field = match button {
+            XboxButton::A => &mut self.a,
+            XboxButton::B => &mut self.b,
+            XboxButton::X => &mut self.x,
+            XboxButton::Y => &mut self.y,
+            XboxButton::LeftBumper => &mut self.left_bumper,
+            XboxButton::RightBumper => &mut self.right_bumper,
+            XboxButton::Back => &mut self.back,
+            XboxButton::Start => &mut self.start,
+            XboxButton::Guide => &mut self.guide,
+            XboxButton::LeftThumb => &mut self.left_thumb,
+            XboxButton::RightThumb => &mut self.right_thumb,
+            XboxButton::DpadUp => &mut self.dpad_up,
+            XboxButton::DpadDown => &mut self.dpad_down,
+            XboxButton::DpadLeft => &mut self.dpad_left,
+            XboxButton::DpadRight => &mut self.dpad_right,
+            XboxButton::Touchpad => &mut self.touchpad,
+        };
+        *field = pressed;
+    }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct RumbleState {
     pub large_motor: f32,
     pub small_motor: f32,
+    /// Directional force from constant and periodic effects, -1 (left) to 1 (right)
+    pub force: f32,
+    /// When the game last sent anything, so a silent game can be noticed
+    pub updated: Option<Instant>,
+    /// An effect is still playing on a live FF thread, the way it was asked to.
+    /// A game that plays one endless effect and leaves it isn't silent.
+    pub active: bool,
+}
+
+impl RumbleState {
+    /// When the game was last known to be there, for the watchdog
+    pub fn last_heard(&self, now: Instant) -> Option<Instant> {
+        if self.active { Some(now) } else { self.updated }
+    }
+}
+
+/// An FF effect exactly as the game uploaded it, for handing to the wheel unchanged
+#[derive(Debug, Clone, Copy)]
+pub struct PassthroughEffect {
+    /// FF_CONSTANT, FF_PERIODIC, FF_SPRING or FF_DAMPER
+    pub kind: u16,
+    pub direction: u16,
+    pub trigger: [u8; 4],
+    pub replay: [u8; 4],
+    /// The kernel's ff_effect union, whichever struct `kind` says it is
+    pub params: [u8; 32],
 }
+
+/// FF traffic from the game that should go straight to the wheel. Ids are the game's.
+#[derive(Debug, Clone)]
+pub enum FfPassthrough {
+    Upload { id: i16, effect: PassthroughEffect },
+    Erase(i16),
+    Play { id: i16, count: i32 },
+    Gain(u16),
+}
+
 pub trait VirtualController: Send {
     fn update(&mut self, state: &XboxControllerState) -> anyhow::Result<()>;
     fn get_rumble(&mut self) -> anyhow::Result<RumbleState>;
     fn is_connected(&self) -> bool;
+
+    /// FF traffic queued for the wheel since the last call
+    fn take_passthrough(&mut self) -> Vec<FfPassthrough> {
+        Vec::new()
+    }
+    /// The /dev/input node the gamepad showed up as, where there is one
+    fn event_node(&self) -> Option<&Path> {
+        None
+    }
 }
@@ -0,0 +1,269 @@
+use super::uinput_ffi::*;
+use std::fs::{File, OpenOptions};
+use std::ffi::CStr;
+use std::io::Write;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+
+/// Effects a game can have uploaded at once
+const FF_EFFECTS_MAX: u32 = 16;
+
+/// An absolute axis and its range
+#[derive(Debug, Clone, Copy)]
+pub struct AbsAxis {
+    pub code: u16,
+    pub min: i32,
+    pub max: i32,
+    /// Units per mm (per g or degree per second for motion sensors), 0 if unknown.
+    /// Kernels older than 4.5 can't be told it.
+    pub resolution: i32,
+}
+
+impl AbsAxis {
+    pub const fn new(code: u16, min: i32, max: i32) -> Self {
+        Self { code, min, max, resolution: 0 }
+    }
+
+    pub const fn with_resolution(self, resolution: i32) -> Self {
+        Self { resolution, ..self }
+    }
+}
+
+/// Everything the kernel needs to know to create a uinput device
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceSpec<'a> {
+    pub name: &'a str,
+    pub vendor: u16,
+    pub product: u16,
+    pub version: u16,
+    pub keys: &'a [u16],
+    pub rel: &'a [u16],
+    pub axes: &'a [AbsAxis],
+    pub misc: &'a [u16],
+    pub props: &'a [u16],
+    /// FF effect types to advertise, none leaves EV_FF off
+    pub ff: &'a [u16],
+}
+
+/// A created uinput device, destroyed when dropped
+pub struct UinputDevice {
+    file: File,
+    /// The /dev/input/eventN node the kernel made for it
+    event_node: Option<PathBuf>,
+}
+
+impl UinputDevice {
+    pub fn create(spec: &DeviceSpec) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(UINPUT_PATH)
+            .map_err(|e| anyhow::anyhow!(
+                "Failed to open {}: {}. Try: sudo chmod 666 /dev/uinput",
+                UINPUT_PATH, e
+            ))?;
+
+        let fd = file.as_raw_fd();
+
+        // Make sure uinput can't block when we try to poll things
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags < 0 {
+                return Err(anyhow::anyhow!("Failed to get uinput file flags"));
+            }
+            if libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+                return Err(anyhow::anyhow!("Failed to set uinput non-blocking mode"));
+            }
+        }
+
+        let set_bit = |request: libc::c_ulong, bit: u16, what: &str| -> anyhow::Result<()> {
+            if unsafe { libc::ioctl(fd, request, bit as libc::c_int) } < 0 {
+                return Err(anyhow::anyhow!("Failed to set {} {}: {}", what, bit, std::io::Error::last_os_error()));
+            }
+            Ok(())
+        };
+
+        // Enable event types
+        set_bit(UI_SET_EVBIT, EV_SYN, "event type")?;
+        if !spec.keys.is_empty() {
+            set_bit(UI_SET_EVBIT, EV_KEY, "event type")?;
+        }
+        if !spec.rel.is_empty() {
+            set_bit(UI_SET_EVBIT, EV_REL, "event type")?;
+        }
+        if !spec.axes.is_empty() {
+            set_bit(UI_SET_EVBIT, EV_ABS, "event type")?;
+        }
+        if !spec.misc.is_empty() {
+            set_bit(UI_SET_EVBIT, EV_MSC, "event type")?;
+        }
+        if !spec.ff.is_empty() {
+            set_bit(UI_SET_EVBIT, EV_FF, "event type")?;
+        }
+
+        for &key in spec.keys {
+            set_bit(UI_SET_KEYBIT, key, "button")?;
+        }
+        for &rel in spec.rel {
+            set_bit(UI_SET_RELBIT, rel, "relative axis")?;
+        }
+        for axis in spec.axes {
+            set_bit(UI_SET_ABSBIT, axis.code, "axis")?;
+        }
+        for &misc in spec.misc {
+            set_bit(UI_SET_MSCBIT, misc, "misc event")?;
+        }
+        for &prop in spec.props {
+            set_bit(UI_SET_PROPBIT, prop, "property")?;
+        }
+        for &ff in spec.ff {
+            set_bit(UI_SET_FFBIT, ff, "FF bit")?;
+        }
+
+        if !Self::setup(fd, spec)? {
+            log::debug!("uinput is too old for UI_DEV_SETUP, writing the device struct instead");
+            Self::write_user_dev(fd, spec)?;
+        }
+
+        if unsafe { libc::ioctl(fd, UI_DEV_CREATE) } < 0 {
+            return Err(anyhow::anyhow!("Failed to create device: {}", std::io::Error::last_os_error()));
+        }
+
+        let event_node = Self::find_event_node(fd);
+        match event_node {
+            Some(ref node) => log::info!("Uinput device created: {} at {}", spec.name, node.display()),
+            None => log::info!("Uinput device created: {}", spec.name),
+        }
+        Ok(Self { file, event_node })
+    }
+
+    /// Describe the device with UI_DEV_SETUP and UI_ABS_SETUP, false if the kernel doesn't have them
+    fn setup(fd: RawFd, spec: &DeviceSpec) -> anyhow::Result<bool> {
+        let mut setup = UinputSetup {
+            id: Self::input_id(spec),
+            name: [0; 80],
+            ff_effects_max: if spec.ff.is_empty() { 0 } else { FF_EFFECTS_MAX },
+        };
+        copy_name(&mut setup.name, spec.name);
+
+        if unsafe { libc::ioctl(fd, UI_DEV_SETUP, &setup as *const UinputSetup) } < 0 {
+            let error = std::io::Error::last_os_error();
+            return match error.raw_os_error() {
+                Some(libc::EINVAL) | Some(libc::ENOTTY) => Ok(false),
+                _ => Err(anyhow::anyhow!("Failed to set up device: {}", error)),
+            };
+        }
+
+        for axis in spec.axes {
+            let abs = UinputAbsSetup {
+                code: axis.code,
+                // No fuzz or flat, the kernel would add its own filtering and deadzone on top of ours
+                absinfo: InputAbsinfo {
+                    minimum: axis.min,
+                    maximum: axis.max,
+                    resolution: axis.resolution,
+                    ..Default::default()
+                },
+            };
+            if unsafe { libc::ioctl(fd, UI_ABS_SETUP, &abs as *const UinputAbsSetup) } < 0 {
+                return Err(anyhow::anyhow!("Failed to set up axis {}: {}", axis.code, std::io::Error::last_os_error()));
+            }
+        }
+        Ok(true)
+    }
+
+    /// The pre 4.5 way, which has no room for resolutions
+    fn write_user_dev(fd: RawFd, spec: &DeviceSpec) -> anyhow::Result<()> {
+        unsafe {
+            let mut dev: UinputUserDev = std::mem::zeroed();
+            copy_name(&mut dev.name, spec.name);
+            dev.id = Self::input_id(spec);
+            if !spec.ff.is_empty() {
+                dev.ff_effects_max = FF_EFFECTS_MAX;
+            }
+
+            for axis in spec.axes {
+                dev.absmin[axis.code as usize] = axis.min;
+                dev.absmax[axis.code as usize] = axis.max;
+                // Same as the setup path, no kernel side filtering or deadzone
+                dev.absfuzz[axis.code as usize] = 0;
+                dev.absflat[axis.code as usize] = 0;
+            }
+
+            let dev_bytes = std::slice::from_raw_parts(
+                &dev as *const _ as *const u8,
+                std::mem::size_of::<UinputUserDev>()
+            );
+
+            if libc::write(fd, dev_bytes.as_ptr() as *const libc::c_void, dev_bytes.len()) < 0 {
+                return Err(anyhow::anyhow!("Failed to write device struct"));
+            }
+        }
+        Ok(())
+    }
+
+    fn input_id(spec: &DeviceSpec) -> InputId {
+        InputId {
+            bustype: 0x03, // BUS_USB
+            vendor: spec.vendor,
+            product: spec.product,
+            version: spec.version,
+        }
+    }
+
+    /// Ask uinput for the device's sysfs name (inputN) and look for the event node under it
+    fn find_event_node(fd: RawFd) -> Option<PathBuf> {
+        let mut sysname = [0u8; SYSNAME_LEN];
+        if unsafe { libc::ioctl(fd, UI_GET_SYSNAME, sysname.as_mut_ptr()) } < 0 {
+            log::debug!("Failed to get uinput device name: {}", std::io::Error::last_os_error());
+            return None;
+        }
+        let sysname = CStr::from_bytes_until_nul(&sysname).ok()?.to_str().ok()?;
+        std::fs::read_dir(Path::new("/sys/devices/virtual/input").join(sysname))
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .find(|entry| entry.file_name().to_string_lossy().starts_with("event"))
+            .map(|entry| Path::new("/dev/input").join(entry.file_name()))
+    }
+
+    pub fn event_node(&self) -> Option<&Path> {
+        self.event_node.as_deref()
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+
+    pub fn write_event(&mut self, type_: u16, code: u16, value: i32) -> anyhow::Result<()> {
+        let event = InputEvent::new(type_, code, value);
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                &event as *const _ as *const u8,
+                std::mem::size_of::<InputEvent>()
+            )
+        };
+        self.file.write_all(bytes)?;
+        Ok(())
+    }
+
+    pub fn sync(&mut self) -> anyhow::Result<()> {
+        self.write_event(EV_SYN, SYN_REPORT, 0)?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Copy as much of `name` as fits, leaving the last byte for the terminator
+fn copy_name(dest: &mut [u8; 80], name: &str) {
+    let name = name.as_bytes();
+    let len = name.len().min(dest.len() - 1);
+    dest[..len].copy_from_slice(&name[..len]);
+}
+
+impl Drop for UinputDevice {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = libc::ioctl(self.file.as_raw_fd(), UI_DEV_DESTROY);
+        }
+    }
+}
@@ -3,14 +3,37 @@ pub const UINPUT_PATH: &str = "/dev/uinput";
 // Event stuff
 pub const EV_SYN: u16 = 0x00;
 pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
 pub const EV_ABS: u16 = 0x03;
+pub const EV_MSC: u16 = 0x04;
 pub const EV_FF: u16 = 0x15;
 pub const EV_UINPUT: u16 = 0x0101;
 
 pub const SYN_REPORT: u16 = 0x00;
 
+pub const MSC_TIMESTAMP: u16 = 0x05;
+
+// Input properties
+pub const INPUT_PROP_POINTER: u16 = 0x00;
+pub const INPUT_PROP_BUTTONPAD: u16 = 0x02;
+pub const INPUT_PROP_ACCELEROMETER: u16 = 0x06;
+
 // Force feedback effect types
 pub const FF_RUMBLE: u16 = 0x50;
+pub const FF_PERIODIC: u16 = 0x51;
+pub const FF_CONSTANT: u16 = 0x52;
+pub const FF_SPRING: u16 = 0x53;
+pub const FF_DAMPER: u16 = 0x55;
+
+// Periodic waveforms
+pub const FF_SQUARE: u16 = 0x58;
+pub const FF_TRIANGLE: u16 = 0x59;
+pub const FF_SINE: u16 = 0x5a;
+pub const FF_SAW_UP: u16 = 0x5b;
+pub const FF_SAW_DOWN: u16 = 0x5c;
+
+// Device wide FF controls, sent as EV_FF events
+pub const FF_GAIN: u16 = 0x60;
 
 // UI event codes
 pub const UI_FF_UPLOAD: u16 = 1;
@@ -29,6 +52,32 @@ pub const BTN_MODE: u16 = 0x13c;
 pub const BTN_THUMBL: u16 = 0x13d;
 pub const BTN_THUMBR: u16 = 0x13e;
 
+// PlayStation buttons, named by position like the kernel's playstation driver does
+pub const BTN_SOUTH: u16 = 0x130;
+pub const BTN_EAST: u16 = 0x131;
+pub const BTN_NORTH: u16 = 0x133;
+pub const BTN_WEST: u16 = 0x134;
+pub const BTN_TL2: u16 = 0x138;
+pub const BTN_TR2: u16 = 0x139;
+
+// Joystick buttons, BTN_TRIGGER to BTN_DEAD then the BTN_TRIGGER_HAPPY range
+pub const BTN_JOYSTICK: u16 = 0x120;
+pub const BTN_JOYSTICK_COUNT: u16 = 16;
+pub const BTN_TRIGGER_HAPPY1: u16 = 0x2c0;
+
+// Mouse buttons, the touchpad click is BTN_LEFT too
+pub const BTN_LEFT: u16 = 0x110;
+pub const BTN_RIGHT: u16 = 0x111;
+pub const BTN_MIDDLE: u16 = 0x112;
+
+// Touchpad buttons
+pub const BTN_TOOL_FINGER: u16 = 0x145;
+pub const BTN_TOUCH: u16 = 0x14a;
+
+// Relative axes
+pub const REL_X: u16 = 0x00;
+pub const REL_Y: u16 = 0x01;
+
 // Absolute axes
 pub const ABS_X: u16 = 0x00;
 pub const ABS_Y: u16 = 0x01;
@@ -36,16 +85,31 @@ pub const ABS_Z: u16 = 0x02;
 pub const ABS_RX: u16 = 0x03;
 pub const ABS_RY: u16 = 0x04;
 pub const ABS_RZ: u16 = 0x05;
+pub const ABS_THROTTLE: u16 = 0x06;
+pub const ABS_RUDDER: u16 = 0x07;
 pub const ABS_HAT0X: u16 = 0x10;
 pub const ABS_HAT0Y: u16 = 0x11;
+pub const ABS_MT_SLOT: u16 = 0x2f;
+pub const ABS_MT_POSITION_X: u16 = 0x35;
+pub const ABS_MT_POSITION_Y: u16 = 0x36;
+pub const ABS_MT_TRACKING_ID: u16 = 0x39;
 
 // uinput ioctl codes (for x86_64 Linux)
 pub const UI_DEV_CREATE: libc::c_ulong = 0x5501;
 pub const UI_DEV_DESTROY: libc::c_ulong = 0x5502;
 pub const UI_SET_EVBIT: libc::c_ulong = 0x40045564;
 pub const UI_SET_KEYBIT: libc::c_ulong = 0x40045565;
+pub const UI_SET_RELBIT: libc::c_ulong = 0x40045566;
 pub const UI_SET_ABSBIT: libc::c_ulong = 0x40045567;
+pub const UI_SET_MSCBIT: libc::c_ulong = 0x40045568;
 pub const UI_SET_FFBIT: libc::c_ulong = 0x4004556b;
+pub const UI_SET_PROPBIT: libc::c_ulong = 0x4004556e;
+// Setup ioctls from uinput version 5 (Linux 4.5), older kernels only take a written UinputUserDev
+pub const UI_DEV_SETUP: libc::c_ulong = 0x405c5503;
+pub const UI_ABS_SETUP: libc::c_ulong = 0x401c5504;
+/// UI_GET_SYSNAME for a SYSNAME_LEN byte buffer
+pub const UI_GET_SYSNAME: libc::c_ulong = 0x8040552c;
+pub const SYSNAME_LEN: usize = 64;
 
 // Force feedback ioctl codes
 pub const UI_BEGIN_FF_UPLOAD: libc::c_ulong = 0xc06855c8;
@@ -91,6 +155,35 @@ pub struct UinputUserDev {
     pub absflat: [i32; 64],
 }
 
+#[repr(C)]
+pub struct UinputSetup {
+    pub id: InputId,
+    pub name: [u8; 80],
+    pub ff_effects_max: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct InputAbsinfo {
+    pub value: i32,
+    pub minimum: i32,
+    pub maximum: i32,
+    pub fuzz: i32,
+    pub flat: i32,
+    /// Units per mm, or per g and per degree per second for motion sensors
+    pub resolution: i32,
+}
+
+#[repr(C)]
+pub struct UinputAbsSetup {
+    pub code: u16,
+    pub absinfo: InputAbsinfo,
+}
+
+// The ioctl numbers above have these sizes baked in
+const _: () = assert!(std::mem::size_of::<UinputSetup>() == 92);
+const _: () = assert!(std::mem::size_of::<UinputAbsSetup>() == 28);
+
 #[repr(C)]
 #[derive(Default)]
 pub struct InputId {
@@ -100,7 +193,6 @@ pub struct InputId {
     pub version: u16,
 }
 
-// We only really care about the rumble effect since this is what roblox sends
 #[repr(C)]
 pub struct FFEffect {
     pub type_: u16,
@@ -112,6 +204,37 @@ pub struct FFEffect {
     pub u: [u8; 48],       // union (needs to be big enough for ff_periodic_effect)
 }
 
+impl FFEffect {
+    /// How long each play lasts in ms, 0 is forever
+    pub fn replay_length(&self) -> u16 {
+        u16::from_ne_bytes([self.replay[0], self.replay[1]])
+    }
+
+    /// Wait before each play in ms
+    pub fn replay_delay(&self) -> u16 {
+        u16::from_ne_bytes([self.replay[2], self.replay[3]])
+    }
+
+    /// FF_SQUARE, FF_SINE..., only meaningful for FF_PERIODIC
+    pub fn waveform(&self) -> u16 {
+        u16::from_ne_bytes([self.u[0], self.u[1]])
+    }
+
+    /// Read the union as `T`, which must be the struct for this effect's type
+    pub unsafe fn params<T>(&self) -> T {
+        std::ptr::read_unaligned(self.u.as_ptr() as *const T)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FFEnvelope {
+    pub attack_length: u16,
+    pub attack_level: u16,
+    pub fade_length: u16,
+    pub fade_level: u16,
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Default)]
 pub struct FFRumbleEffect {
@@ -119,6 +242,25 @@ pub struct FFRumbleEffect {
     pub weak_magnitude: u16,
 }
 
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FFConstantEffect {
+    pub level: i16,
+    pub envelope: FFEnvelope,
+}
+
+// Leaves out custom_len/custom_data at the end, FF_CUSTOM isn't advertised
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FFPeriodicEffect {
+    pub waveform: u16,
+    pub period: u16,
+    pub magnitude: i16,
+    pub offset: i16,
+    pub phase: u16,
+    pub envelope: FFEnvelope,
+}
+
 #[repr(C)]
 pub struct UinputFFUpload {
     pub request_id: u32,
@@ -1,6 +1,7 @@
 use super::{RumbleState, VirtualController, XboxControllerState};
 use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
+use std::time::Instant;
 use vigem_client::{Client, XGamepad, XButtons, Xbox360Wired, TargetId};
 
 pub struct VirtualXboxController {
@@ -41,6 +42,7 @@ impl VirtualXboxController {
                     if let Ok(mut state) = rumble_state_clone.lock() {
                         state.large_motor = data.large_motor as f32 / 255.0;
                         state.small_motor = data.small_motor as f32 / 255.0;
+                        state.updated = Some(Instant::now());
                         log::trace!("Rumble update: large={:.2}, small={:.2}",
                                    state.large_motor, state.small_motor);
                     }